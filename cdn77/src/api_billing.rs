@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Cdn77Client, Cdn77Error};
+
+// Docs: https://client.cdn77.com/support/api-reference/v3/billing
+
+impl Cdn77Client {
+	/// Gets the current credit balance of the account
+	pub async fn credit_balance(&self) -> Result<GetCreditBalanceResponse, Cdn77Error> {
+		let request_url = self.url("/credit-balance");
+		self.send_request::<GetCreditBalanceResponse>(self.http.get(request_url)).await
+	}
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GetCreditBalanceResponse {
+	pub current_credit: f32,
+	/// Unix timestamp
+	pub credit_expires_at: i64,
+	pub credit_spent_in_30_days: f32,
+}
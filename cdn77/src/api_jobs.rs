@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Cdn77Client, Cdn77Error, ResourceId};
+use crate::client::RequestKind;
+
+// Docs: https://client.cdn77.com/support/api-reference/v3/jobs
+
+/// The API rejects purge and prefetch jobs with more paths than this
+pub const MAX_PATHS_PER_JOB: usize = 2000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobType {
+	Prefetch,
+	Purge,
+	PurgeAll,
+}
+
+impl Display for JobType {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let value = match self {
+			JobType::Prefetch => "prefetch",
+			JobType::Purge => "purge",
+			JobType::PurgeAll => "purge-all",
+		};
+		write!(f, "{}", value)
+	}
+}
+
+impl FromStr for JobType {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"prefetch" => Ok(JobType::Prefetch),
+			"purge" => Ok(JobType::Purge),
+			"purge-all" => Ok(JobType::PurgeAll),
+			_ => Err("Invalid job type"),
+		}
+	}
+}
+
+impl Cdn77Client {
+	/// Lists all jobs of the given type for a resource
+	pub async fn list_jobs(&self, resource_id: ResourceId, job_type: JobType) -> Result<Vec<ListJobDetail>, Cdn77Error> {
+		let request_url = self.url(&format!("/cdn/{}/job-log/{}", resource_id, job_type));
+		self.send_request::<Vec<ListJobDetail>>(self.http.get(request_url)).await
+	}
+
+	/// Gets the details of a single job, including the list of paths
+	pub async fn job_detail(&self, resource_id: ResourceId, job_id: &str) -> Result<GetJobDetailsResponse, Cdn77Error> {
+		let request_url = self.url(&format!("/cdn/{}/job/{}", resource_id, job_id));
+		self.send_request::<GetJobDetailsResponse>(self.http.get(request_url)).await
+	}
+
+	/// Prefetches the given paths on a resource
+	pub async fn prefetch(&self, resource_id: ResourceId, request: &PrefetchRequest) -> Result<PrefetchResponse, Cdn77Error> {
+		let request_url = self.url(&format!("/cdn/{}/job/prefetch", resource_id));
+		self.send_request_of_kind::<PrefetchResponse>(self.http.post(request_url).json(request), RequestKind::NonIdempotentMutation).await
+	}
+
+	/// Purges the given paths from a resource, paths may contain wildcards (*)
+	pub async fn purge(&self, resource_id: ResourceId, request: &PurgeRequest) -> Result<PurgeResponse, Cdn77Error> {
+		let request_url = self.url(&format!("/cdn/{}/job/purge", resource_id));
+		self.send_request_of_kind::<PurgeResponse>(self.http.post(request_url).json(request), RequestKind::NonIdempotentMutation).await
+	}
+
+	/// Purges all files from a resource
+	pub async fn purge_all(&self, resource_id: ResourceId) -> Result<PurgeAllResponse, Cdn77Error> {
+		let request_url = self.url(&format!("/cdn/{}/job/purge-all", resource_id));
+		self.send_request_of_kind::<PurgeAllResponse>(self.http.post(request_url), RequestKind::NonIdempotentMutation).await
+	}
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ListJobDetail {
+	pub id: String,
+	#[serde(rename = "type")]
+	pub resource_type: String,
+	pub cdn: HashMap<String, ResourceId>,
+	pub paths_count: u64,
+	pub state: String,
+	pub queued_at: String,
+	/// Not set while the job is still queued
+	pub done_at: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GetJobDetailsResponse {
+	pub id: String,
+	#[serde(rename = "type")]
+	pub resource_type: String,
+	pub cdn: HashMap<String, ResourceId>,
+	pub paths: Vec<String>,
+	pub paths_count: u64,
+	pub state: String,
+	pub queued_at: String,
+	/// Not set while the job is still queued
+	pub done_at: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PrefetchRequest {
+	pub paths: Vec<String>,
+	pub upstream_host: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PrefetchResponse {
+	pub id: String,
+	#[serde(rename = "type")]
+	pub resource_type: String,
+	pub cdn: HashMap<String, ResourceId>,
+	pub paths: Vec<String>,
+	pub paths_count: u64,
+	pub state: String,
+	pub queued_at: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PurgeRequest {
+	pub paths: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PurgeResponse {
+	pub id: String,
+	#[serde(rename = "type")]
+	pub resource_type: String,
+	pub cdn: HashMap<String, ResourceId>,
+	pub paths: Vec<String>,
+	pub paths_count: u64,
+	pub state: String,
+	pub queued_at: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PurgeAllResponse {
+	pub id: String,
+	#[serde(rename = "type")]
+	pub resource_type: String,
+	pub cdn: HashMap<String, ResourceId>,
+	pub state: String,
+	pub queued_at: String,
+	/// Not set while the job is still queued
+	pub done_at: Option<String>,
+}
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Cdn77Client, Cdn77Error, ResourceId};
+
+// Docs: https://client.cdn77.com/support/api-reference/v3/cdn-resources
+
+impl Cdn77Client {
+	/// Lists all CDN resources of the account
+	pub async fn list_resources(&self) -> Result<Vec<CdnResource>, Cdn77Error> {
+		let request_url = self.url("/cdn");
+		self.send_request::<Vec<CdnResource>>(self.http.get(request_url)).await
+	}
+
+	/// Gets the details of a single CDN resource, e.g. its CNAMEs
+	pub async fn resource_detail(&self, resource_id: ResourceId) -> Result<CdnResource, Cdn77Error> {
+		let request_url = self.url(&format!("/cdn/{}", resource_id));
+		self.send_request::<CdnResource>(self.http.get(request_url)).await
+	}
+}
+
+/// A CDN resource, only the fields needed by this crate are mapped
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CdnResource {
+	pub id: ResourceId,
+	/// The hostname assigned by CDN77, e.g. `1234567890.rsc.cdn77.org`
+	pub cdn_url: String,
+	/// Custom hostnames pointing to the resource
+	#[serde(default)]
+	pub cnames: Vec<String>,
+	#[serde(default)]
+	pub label: Option<String>,
+	#[serde(default)]
+	pub note: Option<String>,
+}
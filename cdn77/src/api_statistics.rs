@@ -0,0 +1,174 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Cdn77Client, Cdn77Error, ResourceId};
+
+// Docs: https://client.cdn77.com/support/api-reference/v3/statistics
+
+/// Stat types which can be used with the `sum*` operations
+pub const SUM_TYPES: [&str; 4] = ["headers", "traffic", "hit-miss", "costs"];
+
+// TODO This is a tad of an overkill, we're converting string => enum => string. Maybe just validate string instead?
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GetStatsType {
+	Bandwidth,
+	Costs,
+	Headers,
+	HeadersDetail,
+	HitMiss,
+	HitMissDetail,
+	Traffic,
+	TrafficDetail,
+}
+
+impl Display for GetStatsType {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let value = match self {
+			GetStatsType::Bandwidth => "bandwidth",
+			GetStatsType::Costs => "costs",
+			GetStatsType::Headers => "headers",
+			GetStatsType::HeadersDetail => "headers-details",
+			GetStatsType::HitMiss => "hit-miss",
+			GetStatsType::HitMissDetail => "hit-miss-detail",
+			GetStatsType::Traffic => "traffic",
+			GetStatsType::TrafficDetail => "traffic-detail",
+		};
+		write!(f, "{}", value)
+	}
+}
+
+impl FromStr for GetStatsType {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"bandwidth" => Ok(GetStatsType::Bandwidth),
+			"costs" => Ok(GetStatsType::Costs),
+			"headers" => Ok(GetStatsType::Headers),
+			"headers-detail" => Ok(GetStatsType::HeadersDetail),
+			"hit-miss" => Ok(GetStatsType::HitMiss),
+			"hit-miss-detail" => Ok(GetStatsType::HitMissDetail),
+			"traffic" => Ok(GetStatsType::Traffic),
+			"traffic-detail" => Ok(GetStatsType::TrafficDetail),
+			_ => Err("Invalid stat type"),
+		}
+	}
+}
+
+impl Cdn77Client {
+	/// Retrieves stats of the given type, the raw JSON is returned as its layout depends on the stat type
+	pub async fn get_stats(&self, stat_type: GetStatsType, request: &GetStatsRequest) -> Result<Value, Cdn77Error> {
+		let request_url = self.url(&format!("/stats/{}", stat_type));
+		self.send_request::<Value>(self.http.post(request_url).json(request)).await
+	}
+
+	/// Gets the bandwidth's 95th percentile
+	pub async fn bandwidth_95th_percentile(&self, request: &Bandwidth95PercentileRequest) -> Result<Bandwidth95PercentileResponse, Cdn77Error> {
+		let request_url = self.url("/stats/bandwidth/percentile");
+		self.send_request::<Bandwidth95PercentileResponse>(self.http.post(request_url).json(request)).await
+	}
+
+	/// Retrieves stats of the given type grouped by CDN resource
+	pub async fn stats_by_resource(&self, stat_type: GetStatsType, request: &StatsByResourceRequest) -> Result<Value, Cdn77Error> {
+		let request_url = self.url(&format!("/stats/cdns/{}", stat_type));
+		self.send_request::<Value>(self.http.post(request_url).json(request)).await
+	}
+
+	/// Retrieves the sum of a stat type (see [`SUM_TYPES`]) per CDN resource
+	pub async fn sum_by_resource(&self, stat_type: &str, request: &SumByResourceRequest) -> Result<Value, Cdn77Error> {
+		let request_url = self.url(&format!("/stats/cdns/sum/{}", stat_type));
+		self.send_request::<Value>(self.http.post(request_url).json(request)).await
+	}
+
+	/// Retrieves stats of the given type grouped by data center
+	pub async fn stats_by_data_center(&self, stat_type: GetStatsType, request: &StatsByDataCenterRequest) -> Result<Value, Cdn77Error> {
+		let request_url = self.url(&format!("/stats/datacenters/{}", stat_type));
+		self.send_request::<Value>(self.http.post(request_url).json(request)).await
+	}
+
+	/// Retrieves the sum of a stat type (see [`SUM_TYPES`]) per data center
+	pub async fn sum_by_data_center(&self, stat_type: &str, request: &SumByDataCenterRequest) -> Result<Value, Cdn77Error> {
+		let request_url = self.url(&format!("/stats/datacenters/sum/{}", stat_type));
+		self.send_request::<Value>(self.http.post(request_url).json(request)).await
+	}
+
+	/// Retrieves the sum of a stat type (see [`SUM_TYPES`])
+	pub async fn sum(&self, stat_type: &str, request: &SumRequest) -> Result<SumResponse, Cdn77Error> {
+		let request_url = self.url(&format!("/stats/sum/{}", stat_type));
+		self.send_request::<SumResponse>(self.http.post(request_url).json(request)).await
+	}
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct GetStatsRequest {
+	/// Unix timestamp
+	pub from: i64,
+	/// Unix timestamp
+	pub to: i64,
+	pub cdn_ids: Option<Vec<ResourceId>>,
+	pub location_ids: Option<Vec<String>>,
+	pub aggregation: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Bandwidth95PercentileRequest {
+	pub from: i64,
+	pub to: i64,
+	pub cdn_ids: Option<Vec<ResourceId>>,
+	pub location_ids: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Bandwidth95PercentileResponse {
+	pub percentile: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StatsByResourceRequest {
+	pub from: i64,
+	pub to: i64,
+	pub cdn_ids: Option<Vec<ResourceId>>,
+	pub location_ids: Option<Vec<String>>,
+	pub aggregation: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SumByResourceRequest {
+	pub from: i64,
+	pub to: i64,
+	pub cdn_ids: Option<Vec<ResourceId>>,
+	pub location_ids: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StatsByDataCenterRequest {
+	pub from: i64,
+	pub to: i64,
+	pub cdn_ids: Option<Vec<ResourceId>>,
+	pub location_ids: Option<Vec<String>>,
+	pub aggregation: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SumByDataCenterRequest {
+	pub from: i64,
+	pub to: i64,
+	pub cdn_ids: Option<Vec<ResourceId>>,
+	pub location_ids: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SumRequest {
+	pub from: i64,
+	pub to: i64,
+	pub cdn_ids: Option<Vec<ResourceId>>,
+	pub location_ids: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SumResponse {
+	pub sum: f64,
+}
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Cdn77Client, Cdn77Error};
+
+// Docs: https://client.cdn77.com/support/api-reference/v3/storage-location
+
+impl Cdn77Client {
+	/// Lists all storage locations
+	pub async fn list_storage_locations(&self) -> Result<Vec<StorageListEntry>, Cdn77Error> {
+		let request_url = self.url("/storage-location");
+		self.send_request::<Vec<StorageListEntry>>(self.http.get(request_url)).await
+	}
+
+	/// Gets the details of a single storage location
+	pub async fn storage_location_detail(&self, storage_id: &str) -> Result<StorageDetailResponse, Cdn77Error> {
+		let request_url = self.url(&format!("/storage-location/{}", storage_id));
+		self.send_request::<StorageDetailResponse>(self.http.get(request_url)).await
+	}
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StorageListEntry {
+	pub id: String,
+	pub location: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StorageDetailResponse {
+	pub id: String,
+	pub location: String,
+}
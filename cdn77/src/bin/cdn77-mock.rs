@@ -0,0 +1,52 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use cdn77::mock_server::{InjectedFailure, MockServer, MockServerConfig};
+use cdn77::ResourceId;
+use clap::Parser;
+
+#[derive(Parser)]
+#[clap(
+name = "cdn77-mock",
+version = "0.1",
+author, about,
+long_about = "In-memory mock of the CDN77 V3 API for offline integration tests. Point the client to it via --api-base http://<listen>/v3"
+)]
+struct MockOpts {
+	#[clap(short = 'l', long, default_value = "127.0.0.1:8077")]
+	/// Address to listen on
+	listen: SocketAddr,
+	#[clap(short = 'a', long, env = "CDN77_MOCK_API_TOKEN")]
+	/// (opt) Only accept this API token, defaults to accepting any token
+	api_token: Option<String>,
+	#[clap(short = 'i', long = "resource-id", default_value = "12345")]
+	/// IDs of the CDN resources which exist, can be repeated
+	resource_ids: Vec<ResourceId>,
+	#[clap(long = "purge-all-disabled")]
+	/// (opt) IDs of resources on which purge-all is forbidden, can be repeated
+	purge_all_disabled: Vec<ResourceId>,
+	#[clap(short = 'd', long, default_value = "2")]
+	/// Seconds until a queued job is done
+	job_duration: u64,
+	#[clap(short = 'f', long = "fail")]
+	/// (opt) Inject a failure in the format STATUS[:PATH_PREFIX[:TIMES]], e.g. 429:/cdn/12345/job:2, can be repeated
+	failures: Vec<InjectedFailure>,
+}
+
+#[tokio::main]
+async fn main() {
+	let opts = MockOpts::parse();
+	let config = MockServerConfig {
+		api_token: opts.api_token,
+		resource_ids: opts.resource_ids,
+		purge_all_disabled: opts.purge_all_disabled,
+		job_duration: Duration::from_secs(opts.job_duration),
+		failures: opts.failures,
+	};
+
+	let server = MockServer::start(opts.listen, config).await
+		.unwrap_or_else(|err| panic!("Failed to start mock server on {}: {:?}", opts.listen, err));
+	eprintln!("CDN77 mock API listening, use --api-base {}", server.api_base());
+
+	tokio::signal::ctrl_c().await.ok();
+}
@@ -0,0 +1,450 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::{Certificate, Client, header, Proxy, Request, RequestBuilder, StatusCode, Url};
+use reqwest::header::HeaderMap;
+use serde::de::DeserializeOwned;
+use tracing::{debug, Level, trace, warn};
+
+use crate::{Cdn77Error, DryRunRequest};
+use crate::fixtures::{FixtureMode, Fixtures, header_map_to_tree, REDACTED, request_body};
+use crate::rate_limit::RateLimiter;
+use crate::retry::RetryPolicy;
+
+pub const CDN77_API_BASE: &str = "https://api.cdn77.com/v3";
+const PEM_CERT_END: &str = "-----END CERTIFICATE-----";
+const USER_AGENT: &str = "cdn77-api-cli-client (https://github.com/misternerd/cdn77-api-cli-client)";
+
+/// A client for the CDN77 API. The API operations are implemented in the `api_*` modules.
+#[derive(Clone, Debug)]
+pub struct Cdn77Client {
+	pub(crate) http: Client,
+	api_base: String,
+	fixtures: Option<Fixtures>,
+	retry_policy: RetryPolicy,
+	rate_limiter: Option<Arc<RateLimiter>>,
+	dry_run: bool,
+}
+
+impl Cdn77Client {
+	/// Creates a new client with the default settings which authenticates all requests with the given API token
+	pub fn new(api_token: &str) -> Result<Self, Cdn77Error> {
+		Self::builder(api_token).build()
+	}
+
+	/// Creates a builder to customize the client, e.g. the API base URL
+	pub fn builder(api_token: &str) -> Cdn77ClientBuilder {
+		Cdn77ClientBuilder {
+			api_token: api_token.to_string(),
+			api_base: CDN77_API_BASE.to_string(),
+			fixture_mode: None,
+			retry_policy: RetryPolicy::default(),
+			rate_limit: None,
+			timeout: None,
+			connect_timeout: None,
+			proxy: None,
+			ca_cert_file: None,
+			dry_run: false,
+		}
+	}
+
+	/// The base URL all request paths are appended to, without a trailing slash
+	pub fn api_base(&self) -> &str {
+		&self.api_base
+	}
+
+	pub(crate) fn url(&self, path: &str) -> String {
+		format!("{}{}", self.api_base, path)
+	}
+
+	/// Sends a read-only request and deserializes a success response, all other responses are mapped to a [`Cdn77Error`]
+	pub(crate) async fn send_request<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Cdn77Error> {
+		self.send_request_of_kind(request, RequestKind::Read).await
+	}
+
+	/// Like [`Self::send_request`], the kind decides whether the request is retried and whether it's sent in dry run mode
+	pub(crate) async fn send_request_of_kind<T: DeserializeOwned>(&self, request: RequestBuilder, kind: RequestKind) -> Result<T, Cdn77Error> {
+		if self.dry_run && kind.is_mutating() {
+			let request = request.build().map_err(Cdn77Error::Transport)?;
+			return Err(Cdn77Error::DryRun(DryRunRequest {
+				method: request.method().to_string(),
+				url: request.url().to_string(),
+				body: request_body(&request).and_then(|body| serde_json::from_str(&body).ok()),
+			}));
+		}
+
+		self.send_request_with_retries(request, kind.is_idempotent()).await
+	}
+
+	async fn send_request_with_retries<T: DeserializeOwned>(&self, request: RequestBuilder, idempotent: bool) -> Result<T, Cdn77Error> {
+		let request = request.build().map_err(Cdn77Error::Transport)?;
+		let mut attempt = 0;
+
+		let (status, body) = loop {
+			let attempt_request = request.try_clone()
+				.ok_or_else(|| Cdn77Error::InvalidConfig("Request body cannot be cloned".to_string()))?;
+
+			let delay = match self.execute(attempt_request).await {
+				Ok((status, headers, body)) if self.retry_policy.should_retry_status(status, idempotent) => {
+					match self.retry_policy.delay(attempt, Some(&headers)) {
+						Some(delay) => delay,
+						None => break (status, body),
+					}
+				}
+				Ok((status, _, body)) => break (status, body),
+				Err(Cdn77Error::Transport(err)) if self.retry_policy.should_retry_transport_error(&err, idempotent) => {
+					match self.retry_policy.delay(attempt, None) {
+						Some(delay) => delay,
+						None => return Err(Cdn77Error::Transport(err)),
+					}
+				}
+				Err(err) => return Err(err),
+			};
+
+			debug!(attempt = attempt + 1, delay_ms = delay.as_millis() as u64, "Retrying request");
+			tokio::time::sleep(delay).await;
+			attempt += 1;
+		};
+
+		if !status.is_success() {
+			return Err(Cdn77Error::from_status(status, request.url().to_string(), body));
+		}
+
+		serde_json::from_str::<T>(&body).map_err(Cdn77Error::Deserialization)
+	}
+
+	/// Logs every request and its response via `tracing`: method, URL, status and latency at debug level, headers and bodies at trace level.
+	/// The token is a default header of the client, so it's never part of the logged request headers, which still document it was sent.
+	async fn execute(&self, request: Request) -> Result<(StatusCode, HeaderMap, String), Cdn77Error> {
+		let method = request.method().clone();
+		let url = request.url().clone();
+		debug!(%method, %url, "Sending request");
+		if tracing::enabled!(Level::TRACE) {
+			let mut headers = header_map_to_tree(request.headers());
+			headers.insert(header::AUTHORIZATION.to_string(), format!("Bearer {}", REDACTED));
+			trace!(%method, %url, ?headers, body = request_body(&request).as_deref().unwrap_or_default(), "Request");
+		}
+
+		let started = Instant::now();
+		let result = self.send(request).await;
+		let latency_ms = started.elapsed().as_millis() as u64;
+
+		match &result {
+			Ok((status, headers, body)) => {
+				debug!(%method, %url, status = status.as_u16(), latency_ms, "Received response");
+				trace!(%method, %url, headers = ?header_map_to_tree(headers), body = body.as_str(), "Response");
+			}
+			Err(err) => debug!(%method, %url, latency_ms, error = %err, "Request failed"),
+		}
+
+		result
+	}
+
+	/// Sends the request over the network, unless fixtures are replayed. Recorded fixtures are written after the response was read, failing to
+	/// write them is only logged.
+	async fn send(&self, request: Request) -> Result<(StatusCode, HeaderMap, String), Cdn77Error> {
+		let url = request.url().as_str();
+		let path = url.strip_prefix(&self.api_base).unwrap_or(url).to_string();
+
+		if let Some(fixtures) = self.fixtures.as_ref().filter(|f| f.is_replay()) {
+			return fixtures.replay(&request, &path);
+		}
+
+		if let Some(rate_limiter) = &self.rate_limiter {
+			rate_limiter.acquire().await;
+		}
+
+		let recorded_request = self.fixtures.as_ref().and_then(|_| request.try_clone());
+		let response = self.http.execute(request).await.map_err(Cdn77Error::Transport)?;
+		let status = response.status();
+		let headers = response.headers().clone();
+		let body = response.text().await.map_err(Cdn77Error::Transport)?;
+
+		// The request was processed already, e.g. a purge job was created, so it must not be reported as failed
+		if let (Some(fixtures), Some(request)) = (&self.fixtures, recorded_request) {
+			if let Err(err) = fixtures.record(&request, &path, status, &headers, &body) {
+				warn!(error = %err, "Cannot record the fixture of a sent request");
+			}
+		}
+
+		Ok((status, headers, body))
+	}
+}
+
+/// What a request does, independent of its HTTP method: stats are read via POST, just like purge jobs are created
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RequestKind {
+	/// Only reads data, it's sent in dry run mode and always retried
+	Read,
+	/// Changes something with every call, e.g. creating a purge job. It's only retried if the API certainly didn't process it.
+	NonIdempotentMutation,
+}
+
+impl RequestKind {
+	/// Mutating requests aren't sent in dry run mode
+	fn is_mutating(self) -> bool {
+		self != RequestKind::Read
+	}
+
+	fn is_idempotent(self) -> bool {
+		self == RequestKind::Read
+	}
+}
+
+/// Builder for a [`Cdn77Client`], created via [`Cdn77Client::builder`]
+#[derive(Clone)]
+pub struct Cdn77ClientBuilder {
+	api_token: String,
+	api_base: String,
+	fixture_mode: Option<FixtureMode>,
+	retry_policy: RetryPolicy,
+	rate_limit: Option<f64>,
+	timeout: Option<Duration>,
+	connect_timeout: Option<Duration>,
+	proxy: Option<String>,
+	ca_cert_file: Option<PathBuf>,
+	dry_run: bool,
+}
+
+/// The token is redacted, so the builder can be logged
+impl fmt::Debug for Cdn77ClientBuilder {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Cdn77ClientBuilder")
+			.field("api_token", &REDACTED)
+			.field("api_base", &self.api_base)
+			.field("fixture_mode", &self.fixture_mode)
+			.field("retry_policy", &self.retry_policy)
+			.field("rate_limit", &self.rate_limit)
+			.field("timeout", &self.timeout)
+			.field("connect_timeout", &self.connect_timeout)
+			.field("proxy", &self.proxy)
+			.field("ca_cert_file", &self.ca_cert_file)
+			.field("dry_run", &self.dry_run)
+			.finish()
+	}
+}
+
+impl Cdn77ClientBuilder {
+	/// Overrides the API base URL (default [`CDN77_API_BASE`]), e.g. to use a proxy/gateway or a local mock server
+	pub fn api_base(mut self, api_base: &str) -> Self {
+		self.api_base = api_base.to_string();
+		self
+	}
+
+	/// Writes every request/response pair to fixture files in the dir, the API token is redacted
+	pub fn record_fixtures(mut self, dir: impl Into<PathBuf>) -> Self {
+		self.fixture_mode = Some(FixtureMode::Record(dir.into()));
+		self
+	}
+
+	/// Serves responses from fixture files in the dir (see [`Self::record_fixtures`]) instead of using the network
+	pub fn replay_fixtures(mut self, dir: impl Into<PathBuf>) -> Self {
+		self.fixture_mode = Some(FixtureMode::Replay(dir.into()));
+		self
+	}
+
+	/// How often a failed request is retried (default 0). Transport errors, 429 and 5xx are retried with a jittered exponential
+	/// backoff or as long as the `Retry-After` header says. Job submissions are only retried if the API certainly didn't process them.
+	pub fn retries(mut self, retries: u32) -> Self {
+		self.retry_policy.retries = retries;
+		self
+	}
+
+	/// The maximum time to wait before a retry (default 30s), a longer `Retry-After` stops retrying
+	pub fn retry_max_wait(mut self, max_wait: Duration) -> Self {
+		self.retry_policy.max_wait = max_wait;
+		self
+	}
+
+	/// Limits the requests per second (including retries) of the client and all its clones, so concurrent tasks stay below the API quota
+	pub fn rate_limit(mut self, requests_per_second: f64) -> Self {
+		self.rate_limit = Some(requests_per_second);
+		self
+	}
+
+	/// Timeout of a single request attempt, from connecting until the response body was read
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = Some(timeout);
+		self
+	}
+
+	pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+		self.connect_timeout = Some(connect_timeout);
+		self
+	}
+
+	/// Sends all requests via this proxy. Without it, the `HTTPS_PROXY`/`HTTP_PROXY` env variables are used if set.
+	pub fn proxy(mut self, proxy: &str) -> Self {
+		self.proxy = Some(proxy.to_string());
+		self
+	}
+
+	/// Trusts the CA certificates in the PEM file in addition to the system ones, e.g. for a TLS intercepting proxy
+	pub fn ca_cert_file(mut self, ca_cert_file: impl Into<PathBuf>) -> Self {
+		self.ca_cert_file = Some(ca_cert_file.into());
+		self
+	}
+
+	/// Requests which change something aren't sent, they fail with [`Cdn77Error::DryRun`] holding the request instead
+	pub fn dry_run(mut self, dry_run: bool) -> Self {
+		self.dry_run = dry_run;
+		self
+	}
+
+	pub fn build(self) -> Result<Cdn77Client, Cdn77Error> {
+		let api_base = validate_api_base(&self.api_base)?;
+		let rate_limiter = match self.rate_limit {
+			Some(rps) if rps.is_finite() && rps > 0.0 => Some(Arc::new(RateLimiter::new(rps))),
+			Some(rps) => return Err(Cdn77Error::InvalidConfig(format!("Rate limit must be a positive number of requests per second, got {}", rps))),
+			None => None,
+		};
+
+		let mut default_headers = header::HeaderMap::new();
+		let token = format!("Bearer {}", self.api_token);
+		let mut token = header::HeaderValue::from_str(token.as_str())
+			.map_err(|_| Cdn77Error::InvalidConfig("API token contains invalid characters".to_string()))?;
+		token.set_sensitive(true);
+		default_headers.insert(header::AUTHORIZATION, token);
+		default_headers.append(header::USER_AGENT, header::HeaderValue::from_static(USER_AGENT));
+
+		let mut http = Client::builder().default_headers(default_headers);
+
+		if let Some(timeout) = self.timeout {
+			http = http.timeout(timeout);
+		}
+		if let Some(connect_timeout) = self.connect_timeout {
+			http = http.connect_timeout(connect_timeout);
+		}
+		if let Some(proxy) = &self.proxy {
+			let proxy = Proxy::all(proxy.as_str())
+				.map_err(|err| Cdn77Error::InvalidConfig(format!("Proxy '{}' is invalid: {}", proxy, err)))?;
+			http = http.proxy(proxy);
+		}
+		if let Some(ca_cert_file) = &self.ca_cert_file {
+			for certificate in read_ca_certs(ca_cert_file)? {
+				http = http.add_root_certificate(certificate);
+			}
+		}
+
+		let http = http.build().map_err(Cdn77Error::Transport)?;
+
+		let fixtures = match self.fixture_mode {
+			Some(mode) => Some(Fixtures::new(mode)?),
+			None => None,
+		};
+
+		Ok(Cdn77Client { http, api_base, fixtures, retry_policy: self.retry_policy, rate_limiter, dry_run: self.dry_run })
+	}
+}
+
+/// The base must be an absolute http(s) URL without query or fragment, as the request paths are appended to it
+fn validate_api_base(api_base: &str) -> Result<String, Cdn77Error> {
+	let url = Url::parse(api_base)
+		.map_err(|err| Cdn77Error::InvalidConfig(format!("API base '{}' is not a valid URL: {}", api_base, err)))?;
+
+	if url.scheme() != "http" && url.scheme() != "https" {
+		return Err(Cdn77Error::InvalidConfig(format!("API base '{}' must use http or https", api_base)));
+	}
+	if url.host_str().is_none() {
+		return Err(Cdn77Error::InvalidConfig(format!("API base '{}' has no host", api_base)));
+	}
+	if url.query().is_some() || url.fragment().is_some() {
+		return Err(Cdn77Error::InvalidConfig(format!("API base '{}' must not contain a query or fragment", api_base)));
+	}
+
+	Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+/// Reads all certificates of a PEM bundle
+fn read_ca_certs(ca_cert_file: &Path) -> Result<Vec<Certificate>, Cdn77Error> {
+	let pem = fs::read_to_string(ca_cert_file)
+		.map_err(|err| Cdn77Error::InvalidConfig(format!("Cannot read CA cert {}: {}", ca_cert_file.display(), err)))?;
+	let certificates = pem.split_inclusive(PEM_CERT_END)
+		.filter(|block| block.contains(PEM_CERT_END))
+		.map(|block| Certificate::from_pem(block.as_bytes())
+			.map_err(|err| Cdn77Error::InvalidConfig(format!("Invalid CA cert in {}: {}", ca_cert_file.display(), err))))
+		.collect::<Result<Vec<_>, _>>()?;
+
+	if certificates.is_empty() {
+		return Err(Cdn77Error::InvalidConfig(format!("No PEM certificate found in {}", ca_cert_file.display())));
+	}
+
+	Ok(certificates)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn builder_debug_redacts_the_token() {
+		let debug = format!("{:?}", Cdn77Client::builder("secret-token").api_base("http://127.0.0.1:8077/v3"));
+
+		assert!(!debug.contains("secret-token"), "{}", debug);
+		assert!(debug.contains(r#"api_token: "<REDACTED>""#) && debug.contains("http://127.0.0.1:8077/v3"), "{}", debug);
+	}
+
+	#[test]
+	fn client_debug_doesnt_contain_the_token() {
+		let debug = format!("{:?}", Cdn77Client::new("secret-token").unwrap());
+
+		assert!(!debug.contains("secret-token"), "{}", debug);
+	}
+
+	#[test]
+	fn validate_api_base_removes_trailing_slashes() {
+		assert_eq!(validate_api_base("https://api.cdn77.com/v3").unwrap(), "https://api.cdn77.com/v3");
+		assert_eq!(validate_api_base("https://api.cdn77.com/v3/").unwrap(), "https://api.cdn77.com/v3");
+		assert_eq!(validate_api_base("http://127.0.0.1:8077/v3//").unwrap(), "http://127.0.0.1:8077/v3");
+		assert_eq!(validate_api_base("https://gateway.example.com").unwrap(), "https://gateway.example.com");
+	}
+
+	#[test]
+	fn validate_api_base_rejects_other_schemes() {
+		for api_base in ["ftp://api.cdn77.com/v3", "file:///tmp/v3", "unix:/var/run/cdn77.sock"] {
+			match validate_api_base(api_base) {
+				Err(Cdn77Error::InvalidConfig(message)) => assert!(message.contains("must use http or https"), "{}", message),
+				result => panic!("Unexpected result for {}: {:?}", api_base, result),
+			}
+		}
+	}
+
+	#[test]
+	fn validate_api_base_rejects_malformed_urls() {
+		for api_base in ["", "api.cdn77.com/v3", "https://", "http://exa mple.com/v3", "https://api.cdn77.com/v3?x=1", "https://api.cdn77.com/v3#top"] {
+			assert!(matches!(validate_api_base(api_base), Err(Cdn77Error::InvalidConfig(_))), "{}", api_base);
+		}
+	}
+
+	fn ca_cert_error(name: &str, content: &str) -> String {
+		let file = std::env::temp_dir().join(format!("cdn77-ca-cert-test-{}-{}.pem", std::process::id(), name));
+		fs::write(&file, content).unwrap();
+		match read_ca_certs(&file) {
+			Err(Cdn77Error::InvalidConfig(message)) => message,
+			result => panic!("Unexpected result for {}: {:?}", name, result.map(|certs| certs.len())),
+		}
+	}
+
+	#[test]
+	fn read_ca_certs_reads_all_certificates_of_a_bundle() {
+		let bundle = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/ca-bundle.pem");
+
+		assert_eq!(read_ca_certs(&bundle).unwrap().len(), 2);
+		assert!(Cdn77Client::builder("token").ca_cert_file(bundle).build().is_ok());
+	}
+
+	#[test]
+	fn read_ca_certs_rejects_missing_and_invalid_files() {
+		match read_ca_certs(Path::new("/nonexistent/ca.pem")) {
+			Err(Cdn77Error::InvalidConfig(message)) => assert!(message.starts_with("Cannot read CA cert /nonexistent/ca.pem"), "{}", message),
+			result => panic!("Unexpected result {:?}", result.map(|certs| certs.len())),
+		}
+		assert!(ca_cert_error("empty", "").starts_with("No PEM certificate found"));
+		assert!(ca_cert_error("no-pem", "just some text").starts_with("No PEM certificate found"));
+		let invalid = "-----BEGIN CERTIFICATE-----\nbm90IGEgY2VydGlmaWNhdGU=\n-----END CERTIFICATE-----\n";
+		assert!(ca_cert_error("invalid", invalid).starts_with("Invalid CA cert"));
+	}
+}
@@ -0,0 +1,150 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Errors returned by the [`Cdn77Client`](crate::Cdn77Client).
+/// The status code based variants follow https://client.cdn77.com/support/api-reference/v3/introduction
+/// Some codes have a different meaning for some API operations (e.g. 403 on purge-all), so callers might want to match on them.
+#[derive(Debug)]
+pub enum Cdn77Error {
+	/// The client couldn't be set up, e.g. because the API token contains invalid characters
+	InvalidConfig(String),
+	/// 401, the API token is missing or invalid
+	Unauthorized { url: String },
+	/// 403, either bad credentials or the operation isn't allowed for the args
+	Forbidden { url: String, body: String },
+	/// 404, the requested entity doesn't exist
+	NotFound { url: String, body: String },
+	/// 422, the API couldn't process the request, the error body is parsed if possible
+	Validation { url: String, error: ApiErrorResponse },
+	/// Any other non-success status code
+	UnexpectedStatus { status: StatusCode, url: String, body: String },
+	/// The request couldn't be sent or the response couldn't be read
+	Transport(reqwest::Error),
+	/// The response body doesn't match the expected model
+	Deserialization(serde_json::Error),
+	/// A record/replay fixture couldn't be written or found
+	Fixture(String),
+	/// The client is in dry run mode, so the request wasn't sent
+	DryRun(DryRunRequest),
+}
+
+impl Cdn77Error {
+	/// Maps a non-success response to the matching variant
+	pub(crate) fn from_status(status: StatusCode, url: String, body: String) -> Self {
+		match status {
+			StatusCode::UNAUTHORIZED => Cdn77Error::Unauthorized { url },
+			StatusCode::FORBIDDEN => Cdn77Error::Forbidden { url, body },
+			StatusCode::NOT_FOUND => Cdn77Error::NotFound { url, body },
+			StatusCode::UNPROCESSABLE_ENTITY => {
+				let error = serde_json::from_str::<ApiErrorResponse>(&body).unwrap_or(ApiErrorResponse {
+					message: Some(body),
+					errors: Value::Null,
+				});
+				Cdn77Error::Validation { url, error }
+			}
+			status => Cdn77Error::UnexpectedStatus { status, url, body },
+		}
+	}
+
+	/// The HTTP status code of the response, if the error was caused by one
+	pub fn status(&self) -> Option<StatusCode> {
+		match self {
+			Cdn77Error::Unauthorized { .. } => Some(StatusCode::UNAUTHORIZED),
+			Cdn77Error::Forbidden { .. } => Some(StatusCode::FORBIDDEN),
+			Cdn77Error::NotFound { .. } => Some(StatusCode::NOT_FOUND),
+			Cdn77Error::Validation { .. } => Some(StatusCode::UNPROCESSABLE_ENTITY),
+			Cdn77Error::UnexpectedStatus { status, .. } => Some(*status),
+			Cdn77Error::InvalidConfig(_)
+			| Cdn77Error::Transport(_)
+			| Cdn77Error::Deserialization(_)
+			| Cdn77Error::Fixture(_)
+			| Cdn77Error::DryRun(_) => None,
+		}
+	}
+
+	/// The URL of the failed request, if it was sent
+	pub fn request_url(&self) -> Option<&str> {
+		match self {
+			Cdn77Error::Unauthorized { url }
+			| Cdn77Error::Forbidden { url, .. }
+			| Cdn77Error::NotFound { url, .. }
+			| Cdn77Error::Validation { url, .. }
+			| Cdn77Error::UnexpectedStatus { url, .. } => Some(url),
+			Cdn77Error::Transport(err) => err.url().map(|url| url.as_str()),
+			Cdn77Error::DryRun(request) => Some(&request.url),
+			Cdn77Error::InvalidConfig(_) | Cdn77Error::Deserialization(_) | Cdn77Error::Fixture(_) => None,
+		}
+	}
+
+	/// The error body of the API, if it could be parsed
+	pub fn api_errors(&self) -> Option<&ApiErrorResponse> {
+		match self {
+			Cdn77Error::Validation { error, .. } => Some(error),
+			_ => None,
+		}
+	}
+}
+
+impl Display for Cdn77Error {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Cdn77Error::InvalidConfig(msg) => write!(f, "Invalid client configuration: {}", msg),
+			Cdn77Error::Unauthorized { .. } => write!(f, "Got 401/unauthorized. Please check your credentials."),
+			Cdn77Error::Forbidden { .. } => write!(f, "Got 403/forbidden. Please check your credentials or the API operation args."),
+			Cdn77Error::NotFound { .. } => write!(f, "The requested resource was not found. Please validate your args."),
+			Cdn77Error::Validation { error, .. } => {
+				write!(f, "Received 422/UnprocessableEntity. This might be an issue with this client, please check for an update.")?;
+				if let Some(message) = &error.message {
+					write!(f, " message={}", message)?;
+				}
+				if !error.errors.is_null() {
+					write!(f, " errors={}", error.errors)?;
+				}
+				Ok(())
+			}
+			Cdn77Error::UnexpectedStatus { status, body, .. } if *status == StatusCode::METHOD_NOT_ALLOWED => {
+				write!(f, "Received 405/MethodNotAllowed. This might be an issue with an outdated client due to API changes: {}", body)
+			}
+			Cdn77Error::UnexpectedStatus { status, body, .. } => {
+				write!(f, "Received unexpected/unknown status code={}, please check the response for an explanation: {}", status, body)
+			}
+			Cdn77Error::Transport(err) => write!(f, "Failed to get response HTTP request, e={:?}", err),
+			Cdn77Error::Deserialization(err) => write!(f, "Failed to deserialize response, e={:?}", err),
+			Cdn77Error::Fixture(msg) => write!(f, "Fixture error: {}", msg),
+			Cdn77Error::DryRun(request) => write!(f, "Dry run, didn't send {} {}", request.method, request.url),
+		}
+	}
+}
+
+impl Error for Cdn77Error {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Cdn77Error::Transport(err) => Some(err),
+			Cdn77Error::Deserialization(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+/// The error body returned by the API, e.g. for 422
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApiErrorResponse {
+	#[serde(default)]
+	pub message: Option<String>,
+	/// The layout differs between operations, so it's kept as raw JSON
+	#[serde(default)]
+	pub errors: Value,
+}
+
+/// A request which wasn't sent because of dry run mode
+#[derive(Clone, Debug, Serialize)]
+pub struct DryRunRequest {
+	pub method: String,
+	pub url: String,
+	/// The JSON body, if the request has one
+	pub body: Option<Value>,
+}
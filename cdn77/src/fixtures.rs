@@ -0,0 +1,173 @@
+//! Record/replay of HTTP fixtures, so real API behaviour can be captured once and used for deterministic tests afterwards.
+//!
+//! Each request/response pair is written to `<dir>/<METHOD>_<path>_<body hash>.<n>.json`, where `n` counts identical requests.
+//! This way, repeated requests (e.g. polling a job) are replayed in the recorded order, the last fixture is reused if a request is repeated more often.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use reqwest::{header, Request, StatusCode};
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{Cdn77Error, fnv1a_hash};
+
+pub(crate) const REDACTED: &str = "<REDACTED>";
+
+#[derive(Clone, Debug)]
+pub(crate) enum FixtureMode {
+	Record(PathBuf),
+	Replay(PathBuf),
+}
+
+/// Keeps track of how often each request was seen, shared between clones of the client
+#[derive(Clone, Debug)]
+pub(crate) struct Fixtures {
+	mode: FixtureMode,
+	counters: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Fixture {
+	request: FixtureRequest,
+	response: FixtureResponse,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct FixtureRequest {
+	method: String,
+	path: String,
+	headers: BTreeMap<String, String>,
+	body: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct FixtureResponse {
+	status: u16,
+	headers: BTreeMap<String, String>,
+	body: String,
+}
+
+impl Fixtures {
+	pub(crate) fn new(mode: FixtureMode) -> Result<Self, Cdn77Error> {
+		match &mode {
+			FixtureMode::Record(dir) => fs::create_dir_all(dir)
+				.map_err(|err| Cdn77Error::Fixture(format!("Cannot create fixture dir {}: {}", dir.display(), err)))?,
+			FixtureMode::Replay(dir) if !dir.is_dir() => {
+				return Err(Cdn77Error::Fixture(format!("Fixture dir {} doesn't exist", dir.display())));
+			}
+			FixtureMode::Replay(_) => {}
+		}
+
+		Ok(Fixtures { mode, counters: Arc::new(Mutex::new(HashMap::new())) })
+	}
+
+	pub(crate) fn is_replay(&self) -> bool {
+		matches!(self.mode, FixtureMode::Replay(_))
+	}
+
+	/// Writes the request/response pair, the path is relative to the API base
+	pub(crate) fn record(&self, request: &Request, path: &str, status: StatusCode, headers: &HeaderMap, body: &str) -> Result<(), Cdn77Error> {
+		let dir = match &self.mode {
+			FixtureMode::Record(dir) => dir,
+			FixtureMode::Replay(_) => return Ok(()),
+		};
+
+		let request_body = request_body(request);
+		let key = fixture_key(request.method().as_str(), path, request_body.as_deref());
+		let index = self.next_index(&key);
+
+		let mut request_headers = header_map_to_tree(request.headers());
+		// The token is a default header of the client, so it's never part of the request itself. Still document it was sent.
+		request_headers.insert(header::AUTHORIZATION.to_string(), REDACTED.to_string());
+
+		let fixture = Fixture {
+			request: FixtureRequest {
+				method: request.method().to_string(),
+				path: path.to_string(),
+				headers: request_headers,
+				body: request_body,
+			},
+			response: FixtureResponse {
+				status: status.as_u16(),
+				headers: header_map_to_tree(headers),
+				body: body.to_string(),
+			},
+		};
+
+		let file = fixture_file(dir, &key, index);
+		let content = serde_json::to_string_pretty(&fixture).map_err(|err| Cdn77Error::Fixture(err.to_string()))?;
+		fs::write(&file, content).map_err(|err| Cdn77Error::Fixture(format!("Cannot write fixture {}: {}", file.display(), err)))
+	}
+
+	/// Looks up the recorded response for the request
+	pub(crate) fn replay(&self, request: &Request, path: &str) -> Result<(StatusCode, HeaderMap, String), Cdn77Error> {
+		let dir = match &self.mode {
+			FixtureMode::Replay(dir) => dir,
+			FixtureMode::Record(_) => return Err(Cdn77Error::Fixture("Client isn't in replay mode".to_string())),
+		};
+
+		let request_body = request_body(request);
+		let key = fixture_key(request.method().as_str(), path, request_body.as_deref());
+		let index = self.next_index(&key);
+
+		// Fall back to the last recorded fixture if the request is repeated more often than during recording
+		let file = (0..=index).rev()
+			.map(|i| fixture_file(dir, &key, i))
+			.find(|f| f.is_file())
+			.ok_or_else(|| Cdn77Error::Fixture(format!("No fixture for {} {} in {}", request.method(), path, dir.display())))?;
+
+		let content = fs::read_to_string(&file)
+			.map_err(|err| Cdn77Error::Fixture(format!("Cannot read fixture {}: {}", file.display(), err)))?;
+		let fixture = serde_json::from_str::<Fixture>(&content)
+			.map_err(|err| Cdn77Error::Fixture(format!("Invalid fixture {}: {}", file.display(), err)))?;
+		let status = StatusCode::from_u16(fixture.response.status)
+			.map_err(|_| Cdn77Error::Fixture(format!("Invalid status in fixture {}", file.display())))?;
+
+		let mut headers = HeaderMap::new();
+		for (name, value) in fixture.response.headers {
+			if let (Ok(name), Ok(value)) = (header::HeaderName::from_bytes(name.as_bytes()), header::HeaderValue::from_str(&value)) {
+				headers.insert(name, value);
+			}
+		}
+
+		Ok((status, headers, fixture.response.body))
+	}
+
+	fn next_index(&self, key: &str) -> usize {
+		let mut counters = self.counters.lock().unwrap();
+		let counter = counters.entry(key.to_string()).or_insert(0);
+		let index = *counter;
+		*counter += 1;
+		index
+	}
+}
+
+pub(crate) fn request_body(request: &Request) -> Option<String> {
+	request.body()
+		.and_then(|b| b.as_bytes())
+		.map(|b| String::from_utf8_lossy(b).to_string())
+}
+
+pub(crate) fn header_map_to_tree(headers: &HeaderMap) -> BTreeMap<String, String> {
+	headers.iter()
+		.map(|(name, value)| {
+			let value = if name == header::AUTHORIZATION { REDACTED.to_string() } else { value.to_str().unwrap_or_default().to_string() };
+			(name.to_string(), value)
+		})
+		.collect()
+}
+
+fn fixture_key(method: &str, path: &str, body: Option<&str>) -> String {
+	let path: String = path.trim_matches('/')
+		.chars()
+		.map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+		.collect();
+	format!("{}_{}_{:016x}", method, path, fnv1a_hash(body.unwrap_or_default().as_bytes()))
+}
+
+fn fixture_file(dir: &Path, key: &str, index: usize) -> PathBuf {
+	dir.join(format!("{}.{}.json", key, index))
+}
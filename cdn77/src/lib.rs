@@ -0,0 +1,28 @@
+//! Library for the [CDN77 V3 API](https://client.cdn77.com/support/api-reference/v3/introduction).
+//!
+//! The entry point is [`Cdn77Client`], which exposes one async method per supported API operation.
+//! The request and response models of each API section are found in the `api_*` modules.
+
+pub use client::{CDN77_API_BASE, Cdn77Client, Cdn77ClientBuilder};
+pub use error::{ApiErrorResponse, Cdn77Error, DryRunRequest};
+
+pub mod api_billing;
+pub mod api_jobs;
+pub mod api_resources;
+pub mod api_statistics;
+pub mod api_storage;
+mod client;
+mod error;
+mod fixtures;
+#[cfg(feature = "mock-server")]
+pub mod mock_server;
+mod rate_limit;
+mod retry;
+
+/// An alias for the resource ID type
+pub type ResourceId = u64;
+
+/// A hash which is stable across Rust versions and platforms, unlike the `DefaultHasher`, so file names derived from it stay the same
+pub fn fnv1a_hash(data: &[u8]) -> u64 {
+	data.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
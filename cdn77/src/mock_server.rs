@@ -0,0 +1,508 @@
+//! An in-memory stand-in for the CDN77 V3 API, which implements the endpoints used by this crate.
+//! It's meant for offline integration tests, e.g. of deploy pipelines, and is also available as the `cdn77-mock` binary.
+//!
+//! Jobs are kept in memory and move from `queued` to `done` once [`MockServerConfig::job_duration`] has passed.
+//! Error statuses can be injected at startup or at runtime via `POST /_mock/failures` with a JSON [`InjectedFailure`],
+//! `DELETE /_mock/failures` removes all pending failures.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use hyper::service::{make_service_fn, service_fn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use crate::api_jobs::{JobType, MAX_PATHS_PER_JOB};
+use crate::api_statistics::SUM_TYPES;
+use crate::ResourceId;
+
+/// The stat types accepted in the URL of the stats operations, other types are answered with 404. These are the values the client sends,
+/// so the mock doesn't verify them against the API.
+const STATS_TYPES: [&str; 8] = ["bandwidth", "costs", "headers", "headers-details", "hit-miss", "hit-miss-detail", "traffic", "traffic-detail"];
+
+/// Settings of the mock server
+#[derive(Clone, Debug)]
+pub struct MockServerConfig {
+	/// If set, requests must authenticate with this token, otherwise any token is accepted
+	pub api_token: Option<String>,
+	/// The CDN resources which exist, all others are answered with 404
+	pub resource_ids: Vec<ResourceId>,
+	/// Resources on which purge-all is answered with 403
+	pub purge_all_disabled: Vec<ResourceId>,
+	/// How long a job stays queued before it's done
+	pub job_duration: Duration,
+	/// Failures which are active from the start
+	pub failures: Vec<InjectedFailure>,
+}
+
+impl Default for MockServerConfig {
+	fn default() -> Self {
+		MockServerConfig {
+			api_token: None,
+			resource_ids: vec![12345],
+			purge_all_disabled: vec![],
+			job_duration: Duration::from_secs(2),
+			failures: vec![],
+		}
+	}
+}
+
+/// Answers matching requests with an error status instead of processing them
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InjectedFailure {
+	/// The status to reply with, e.g. 401, 403, 404, 422, 429 or 5xx
+	pub status: u16,
+	/// Only requests whose path (without `/v3`) starts with this prefix fail, defaults to all requests
+	#[serde(default)]
+	pub path_prefix: Option<String>,
+	/// How many requests fail before the failure is removed, defaults to all requests
+	#[serde(default)]
+	pub times: Option<u32>,
+}
+
+impl FromStr for InjectedFailure {
+	type Err = String;
+
+	/// Parses the format `STATUS[:PATH_PREFIX[:TIMES]]`, e.g. `429:/cdn/12345/job:2`
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parts = s.splitn(3, ':');
+		let status = parts.next().unwrap_or_default().parse::<u16>()
+			.ok()
+			.filter(|s| StatusCode::from_u16(*s).is_ok())
+			.ok_or_else(|| format!("Invalid status in failure '{}'", s))?;
+		let path_prefix = parts.next().filter(|p| !p.is_empty()).map(|p| p.to_string());
+		let times = match parts.next() {
+			Some(t) => Some(t.parse::<u32>().map_err(|_| format!("Invalid times in failure '{}'", s))?),
+			None => None,
+		};
+
+		Ok(InjectedFailure { status, path_prefix, times })
+	}
+}
+
+/// A running mock server, which is shut down when this is dropped
+pub struct MockServer {
+	addr: SocketAddr,
+	state: Arc<Mutex<MockState>>,
+	_shutdown: oneshot::Sender<()>,
+}
+
+impl MockServer {
+	/// Binds to the address (use port 0 for a random port) and serves requests in a background task
+	pub async fn start(addr: SocketAddr, config: MockServerConfig) -> Result<MockServer, hyper::Error> {
+		let state = Arc::new(Mutex::new(MockState::new(config)));
+		let service_state = state.clone();
+		let make_service = make_service_fn(move |_| {
+			let state = service_state.clone();
+			async move {
+				Ok::<_, Infallible>(service_fn(move |request| handle_request(state.clone(), request)))
+			}
+		});
+
+		let server = Server::try_bind(&addr)?.serve(make_service);
+		let addr = server.local_addr();
+		let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+		tokio::spawn(server.with_graceful_shutdown(async {
+			shutdown_rx.await.ok();
+		}));
+
+		Ok(MockServer { addr, state, _shutdown: shutdown })
+	}
+
+	pub fn addr(&self) -> SocketAddr {
+		self.addr
+	}
+
+	/// The URL to use as API base for the [`Cdn77Client`](crate::Cdn77Client)
+	pub fn api_base(&self) -> String {
+		format!("http://{}/v3", self.addr)
+	}
+
+	pub fn inject_failure(&self, failure: InjectedFailure) {
+		self.state.lock().unwrap().failures.push(failure);
+	}
+
+	pub fn clear_failures(&self) {
+		self.state.lock().unwrap().failures.clear();
+	}
+
+	/// The IDs of all jobs created so far, oldest first
+	pub fn job_ids(&self) -> Vec<String> {
+		self.state.lock().unwrap().jobs.iter().map(|j| j.id.clone()).collect()
+	}
+}
+
+async fn handle_request(state: Arc<Mutex<MockState>>, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+	let method = request.method().clone();
+	let path = request.uri().path().to_string();
+	let path = path.strip_prefix("/v3").unwrap_or(&path).to_string();
+	let authorization = request.headers().get(AUTHORIZATION)
+		.and_then(|v| v.to_str().ok())
+		.map(|v| v.to_string());
+	let body = hyper::body::to_bytes(request.into_body()).await.unwrap_or_default();
+
+	let mut state = state.lock().unwrap();
+	Ok(state.handle(&method, &path, authorization.as_deref(), &body))
+}
+
+struct MockJob {
+	id: String,
+	job_type: JobType,
+	resource_id: ResourceId,
+	paths: Vec<String>,
+	queued_at: DateTime<Utc>,
+}
+
+struct MockState {
+	config: MockServerConfig,
+	failures: Vec<InjectedFailure>,
+	jobs: Vec<MockJob>,
+	job_counter: u64,
+}
+
+impl MockState {
+	fn new(config: MockServerConfig) -> Self {
+		MockState {
+			failures: config.failures.clone(),
+			config,
+			jobs: vec![],
+			job_counter: 0,
+		}
+	}
+
+	fn handle(&mut self, method: &Method, path: &str, authorization: Option<&str>, body: &[u8]) -> Response<Body> {
+		if let Some(control_path) = path.strip_prefix("/_mock/") {
+			return self.handle_control(method, control_path, body);
+		}
+
+		if let Some(status) = self.take_injected_failure(path) {
+			return error_response(status, None);
+		}
+
+		if !self.is_authorized(authorization) {
+			return error_response(StatusCode::UNAUTHORIZED, None);
+		}
+
+		let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+		match segments.as_slice() {
+			["credit-balance"] => self.expect_method(method, Method::GET, |_| {
+				let expires_at = Utc::now() + chrono::Duration::days(30);
+				json_response(StatusCode::OK, json!({
+					"current_credit": 1234.5,
+					"credit_expires_at": expires_at.timestamp(),
+					"credit_spent_in_30_days": 42.0,
+				}))
+			}),
+			["storage-location"] => self.expect_method(method, Method::GET, |_| {
+				json_response(StatusCode::OK, Value::Array(mock_storage_locations()))
+			}),
+			["storage-location", storage_id] => self.expect_method(method, Method::GET, |_| {
+				mock_storage_locations().into_iter()
+					.find(|l| l["id"] == *storage_id)
+					.map(|l| json_response(StatusCode::OK, l))
+					.unwrap_or_else(|| error_response(StatusCode::NOT_FOUND, None))
+			}),
+			["cdn"] => self.expect_method(method, Method::GET, |state| {
+				json_response(StatusCode::OK, state.config.resource_ids.iter().map(|id| mock_resource(*id)).collect())
+			}),
+			["cdn", resource_id] => self.expect_method(method, Method::GET, |state| {
+				state.with_resource(resource_id, |_, resource_id| json_response(StatusCode::OK, mock_resource(resource_id)))
+			}),
+			["cdn", resource_id, "job", job_type] if JobType::from_str(job_type).is_ok() => {
+				let job_type = JobType::from_str(job_type).unwrap();
+				self.expect_method(method, Method::POST, |state| {
+					state.with_resource(resource_id, |state, resource_id| state.create_job(resource_id, job_type, body))
+				})
+			}
+			["cdn", resource_id, "job", job_id] => self.expect_method(method, Method::GET, |state| {
+				state.with_resource(resource_id, |state, resource_id| state.job_detail(resource_id, job_id))
+			}),
+			["cdn", resource_id, "job-log", job_type] => self.expect_method(method, Method::GET, |state| {
+				state.with_resource(resource_id, |state, resource_id| match JobType::from_str(job_type) {
+					Ok(job_type) => state.job_log(resource_id, job_type),
+					Err(_) => error_response(StatusCode::NOT_FOUND, None),
+				})
+			}),
+			["stats", "bandwidth", "percentile"] => self.expect_method(method, Method::POST, |_| {
+				with_stats_request(body, |_| json_response(StatusCode::OK, json!({ "percentile": 987654 })))
+			}),
+			["stats", "cdns", "sum", stat_type] => self.expect_method(method, Method::POST, |state| {
+				state.stats(body, stat_type, true, StatsGrouping::Resource)
+			}),
+			["stats", "cdns", stat_type] => self.expect_method(method, Method::POST, |state| {
+				state.stats(body, stat_type, false, StatsGrouping::Resource)
+			}),
+			["stats", "datacenters", "sum", stat_type] => self.expect_method(method, Method::POST, |state| {
+				state.stats(body, stat_type, true, StatsGrouping::DataCenter)
+			}),
+			["stats", "datacenters", stat_type] => self.expect_method(method, Method::POST, |state| {
+				state.stats(body, stat_type, false, StatsGrouping::DataCenter)
+			}),
+			["stats", "sum", stat_type] => self.expect_method(method, Method::POST, |state| {
+				state.stats(body, stat_type, true, StatsGrouping::None)
+			}),
+			["stats", stat_type] => self.expect_method(method, Method::POST, |state| {
+				state.stats(body, stat_type, false, StatsGrouping::None)
+			}),
+			_ => error_response(StatusCode::NOT_FOUND, None),
+		}
+	}
+
+	fn handle_control(&mut self, method: &Method, path: &str, body: &[u8]) -> Response<Body> {
+		match (method, path) {
+			(&Method::POST, "failures") => match serde_json::from_slice::<InjectedFailure>(body) {
+				Ok(failure) if StatusCode::from_u16(failure.status).is_ok() => {
+					self.failures.push(failure);
+					json_response(StatusCode::CREATED, json!({}))
+				}
+				_ => error_response(StatusCode::UNPROCESSABLE_ENTITY, Some(json!({ "failure": ["Expected {status, path_prefix?, times?}"] }))),
+			},
+			(&Method::DELETE, "failures") => {
+				self.failures.clear();
+				json_response(StatusCode::OK, json!({}))
+			}
+			_ => error_response(StatusCode::NOT_FOUND, None),
+		}
+	}
+
+	fn take_injected_failure(&mut self, path: &str) -> Option<StatusCode> {
+		let index = self.failures.iter().position(|f| match &f.path_prefix {
+			Some(prefix) => path.starts_with(prefix.as_str()),
+			None => true,
+		})?;
+		let failure = &mut self.failures[index];
+		let status = StatusCode::from_u16(failure.status).ok();
+
+		if let Some(times) = failure.times.as_mut() {
+			*times = times.saturating_sub(1);
+			if *times == 0 {
+				self.failures.remove(index);
+			}
+		}
+
+		status
+	}
+
+	fn is_authorized(&self, authorization: Option<&str>) -> bool {
+		let token = match authorization.and_then(|a| a.strip_prefix("Bearer ")) {
+			Some(t) if !t.is_empty() => t,
+			_ => return false,
+		};
+
+		match &self.config.api_token {
+			Some(expected) => expected == token,
+			None => true,
+		}
+	}
+
+	fn expect_method<F>(&mut self, method: &Method, expected: Method, handler: F) -> Response<Body>
+		where F: FnOnce(&mut MockState) -> Response<Body> {
+		if *method != expected {
+			return error_response(StatusCode::METHOD_NOT_ALLOWED, None);
+		}
+
+		handler(self)
+	}
+
+	fn with_resource<F>(&mut self, resource_id: &str, handler: F) -> Response<Body>
+		where F: FnOnce(&mut MockState, ResourceId) -> Response<Body> {
+		match resource_id.parse::<ResourceId>() {
+			Ok(id) if self.config.resource_ids.contains(&id) => handler(self, id),
+			_ => error_response(StatusCode::NOT_FOUND, None),
+		}
+	}
+
+	fn create_job(&mut self, resource_id: ResourceId, job_type: JobType, body: &[u8]) -> Response<Body> {
+		let paths = match job_type {
+			JobType::PurgeAll => {
+				if self.config.purge_all_disabled.contains(&resource_id) {
+					return error_response(StatusCode::FORBIDDEN, None);
+				}
+				vec![]
+			}
+			JobType::Prefetch | JobType::Purge => {
+				let paths = serde_json::from_slice::<Value>(body).ok()
+					.and_then(|b| b.get("paths").cloned())
+					.and_then(|p| serde_json::from_value::<Vec<String>>(p).ok());
+
+				match paths {
+					Some(paths) if paths.len() > MAX_PATHS_PER_JOB => {
+						return error_response(StatusCode::UNPROCESSABLE_ENTITY,
+											  Some(json!({ "paths": [format!("At most {} paths are allowed.", MAX_PATHS_PER_JOB)] })));
+					}
+					Some(paths) if !paths.is_empty() => paths,
+					_ => return error_response(StatusCode::UNPROCESSABLE_ENTITY, Some(json!({ "paths": ["At least one path is required."] }))),
+				}
+			}
+		};
+
+		self.job_counter += 1;
+		let job = MockJob {
+			id: format!("00000000-0000-4000-8000-{:012x}", self.job_counter),
+			job_type,
+			resource_id,
+			paths,
+			queued_at: Utc::now(),
+		};
+		let mut response = self.job_json(&job);
+
+		if job_type == JobType::PurgeAll {
+			response.as_object_mut().unwrap().remove("paths");
+			response.as_object_mut().unwrap().remove("paths_count");
+		} else {
+			response.as_object_mut().unwrap().remove("done_at");
+		}
+
+		self.jobs.push(job);
+		json_response(StatusCode::ACCEPTED, response)
+	}
+
+	fn job_detail(&self, resource_id: ResourceId, job_id: &str) -> Response<Body> {
+		self.jobs.iter()
+			.find(|j| j.resource_id == resource_id && j.id == job_id)
+			.map(|j| json_response(StatusCode::OK, self.job_json(j)))
+			.unwrap_or_else(|| error_response(StatusCode::NOT_FOUND, None))
+	}
+
+	fn job_log(&self, resource_id: ResourceId, job_type: JobType) -> Response<Body> {
+		let jobs = self.jobs.iter()
+			.filter(|j| j.resource_id == resource_id && j.job_type == job_type)
+			.map(|j| {
+				let mut job = self.job_json(j);
+				job.as_object_mut().unwrap().remove("paths");
+				job
+			})
+			.collect();
+
+		json_response(StatusCode::OK, Value::Array(jobs))
+	}
+
+	fn job_json(&self, job: &MockJob) -> Value {
+		let done_at = job.queued_at + chrono::Duration::from_std(self.config.job_duration).unwrap_or_else(|_| chrono::Duration::zero());
+		let is_done = Utc::now() >= done_at;
+
+		json!({
+			"id": job.id,
+			"type": job.job_type.to_string(),
+			"cdn": { "id": job.resource_id },
+			"paths": job.paths,
+			"paths_count": job.paths.len(),
+			"state": if is_done { "done" } else { "queued" },
+			"queued_at": job.queued_at.to_rfc3339(),
+			"done_at": if is_done { Value::String(done_at.to_rfc3339()) } else { Value::Null },
+		})
+	}
+
+	fn stats(&self, body: &[u8], stat_type: &str, sum: bool, grouping: StatsGrouping) -> Response<Body> {
+		let is_valid_type = if sum { SUM_TYPES.contains(&stat_type) } else { STATS_TYPES.contains(&stat_type) };
+		if !is_valid_type {
+			return error_response(StatusCode::NOT_FOUND, None);
+		}
+
+		with_stats_request(body, |request| {
+			let value = |factor: i64| -> Value {
+				if sum {
+					json!(1000 * factor)
+				} else {
+					json!({ "data": { request.from.to_string(): 100 * factor, request.to.to_string(): 200 * factor } })
+				}
+			};
+
+			let response = match grouping {
+				StatsGrouping::None if sum => json!({ "sum": 1000.0 }),
+				StatsGrouping::None => value(1),
+				StatsGrouping::Resource => {
+					let resource_ids = request.cdn_ids.clone().unwrap_or_else(|| self.config.resource_ids.clone());
+					let grouped: serde_json::Map<String, Value> = resource_ids.iter()
+						.enumerate()
+						.map(|(i, id)| (id.to_string(), value(i as i64 + 1)))
+						.collect();
+					Value::Object(grouped)
+				}
+				StatsGrouping::DataCenter => {
+					let location_ids = request.location_ids.clone().unwrap_or_else(|| vec!["prague".to_string(), "london".to_string()]);
+					let grouped: serde_json::Map<String, Value> = location_ids.iter()
+						.enumerate()
+						.map(|(i, id)| (id.to_string(), value(i as i64 + 1)))
+						.collect();
+					Value::Object(grouped)
+				}
+			};
+
+			json_response(StatusCode::OK, response)
+		})
+	}
+}
+
+#[derive(Clone, Copy)]
+enum StatsGrouping {
+	None,
+	Resource,
+	DataCenter,
+}
+
+#[derive(Deserialize)]
+struct MockStatsRequest {
+	from: i64,
+	to: i64,
+	cdn_ids: Option<Vec<ResourceId>>,
+	location_ids: Option<Vec<String>>,
+}
+
+fn with_stats_request<F>(body: &[u8], handler: F) -> Response<Body>
+	where F: FnOnce(&MockStatsRequest) -> Response<Body> {
+	match serde_json::from_slice::<MockStatsRequest>(body) {
+		Ok(request) if request.from <= request.to => handler(&request),
+		Ok(_) => error_response(StatusCode::UNPROCESSABLE_ENTITY, Some(json!({ "from": ["Must be before to."] }))),
+		Err(_) => error_response(StatusCode::UNPROCESSABLE_ENTITY, Some(json!({ "from": ["Required."], "to": ["Required."] }))),
+	}
+}
+
+/// Every resource gets a CNAME derived from its ID, e.g. `cdn-12345.example.com`
+fn mock_resource(resource_id: ResourceId) -> Value {
+	json!({
+		"id": resource_id,
+		"cdn_url": format!("{}.rsc.cdn77.org", resource_id),
+		"cnames": [format!("cdn-{}.example.com", resource_id)],
+		"label": format!("Resource {}", resource_id),
+		"note": Value::Null,
+	})
+}
+
+fn mock_storage_locations() -> Vec<Value> {
+	vec![
+		json!({ "id": "push-1.cdn77.com", "location": "Prague" }),
+		json!({ "id": "push-2.cdn77.com", "location": "London" }),
+	]
+}
+
+fn json_response(status: StatusCode, body: Value) -> Response<Body> {
+	Response::builder()
+		.status(status)
+		.header(CONTENT_TYPE, "application/json")
+		.body(Body::from(body.to_string()))
+		.unwrap()
+}
+
+fn error_response(status: StatusCode, errors: Option<Value>) -> Response<Body> {
+	let message = status.canonical_reason().unwrap_or("Error");
+	let body = match errors {
+		Some(errors) => json!({ "message": message, "errors": errors }),
+		None => json!({ "message": message }),
+	};
+	let mut response = json_response(status, body);
+
+	if status == StatusCode::TOO_MANY_REQUESTS {
+		response.headers_mut().insert(RETRY_AFTER, "1".parse().unwrap());
+	}
+
+	response
+}
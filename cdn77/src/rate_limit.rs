@@ -0,0 +1,104 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// A token bucket, which allows bursts of up to one second worth of requests and refills continuously.
+/// It's shared between all clones of a client, so concurrent tasks stay below the limit together.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+	requests_per_second: f64,
+	capacity: f64,
+	bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+	tokens: f64,
+	last_refill: Instant,
+}
+
+impl RateLimiter {
+	pub(crate) fn new(requests_per_second: f64) -> Self {
+		let capacity = requests_per_second.max(1.0);
+
+		RateLimiter {
+			requests_per_second,
+			capacity,
+			bucket: Mutex::new(Bucket { tokens: capacity, last_refill: Instant::now() }),
+		}
+	}
+
+	/// Waits until a request may be sent
+	pub(crate) async fn acquire(&self) {
+		loop {
+			let wait = {
+				let mut bucket = self.bucket.lock().unwrap();
+				let now = Instant::now();
+				let refill = now.duration_since(bucket.last_refill).as_secs_f64() * self.requests_per_second;
+				bucket.tokens = (bucket.tokens + refill).min(self.capacity);
+				bucket.last_refill = now;
+
+				if bucket.tokens >= 1.0 {
+					bucket.tokens -= 1.0;
+					return;
+				}
+
+				Duration::from_secs_f64((1.0 - bucket.tokens) / self.requests_per_second)
+			};
+
+			tokio::time::sleep(wait).await;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Acquires and returns how long that took, the clock only advances while waiting as the tests run with paused time
+	async fn acquire_duration(limiter: &RateLimiter) -> Duration {
+		let start = Instant::now();
+		limiter.acquire().await;
+		start.elapsed()
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn allows_a_burst_of_one_second_worth_of_requests() {
+		let limiter = RateLimiter::new(5.0);
+
+		for _ in 0..5 {
+			assert_eq!(acquire_duration(&limiter).await, Duration::ZERO);
+		}
+		assert_eq!(acquire_duration(&limiter).await, Duration::from_millis(200));
+		assert_eq!(acquire_duration(&limiter).await, Duration::from_millis(200));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn refills_continuously_up_to_the_capacity() {
+		let limiter = RateLimiter::new(5.0);
+		for _ in 0..5 {
+			limiter.acquire().await;
+		}
+
+		tokio::time::sleep(Duration::from_millis(400)).await;
+		assert_eq!(acquire_duration(&limiter).await, Duration::ZERO);
+		assert_eq!(acquire_duration(&limiter).await, Duration::ZERO);
+		assert_eq!(acquire_duration(&limiter).await, Duration::from_millis(200));
+
+		// Idling longer than a second doesn't allow a larger burst
+		tokio::time::sleep(Duration::from_secs(10)).await;
+		for _ in 0..5 {
+			assert_eq!(acquire_duration(&limiter).await, Duration::ZERO);
+		}
+		assert_eq!(acquire_duration(&limiter).await, Duration::from_millis(200));
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn allows_single_requests_below_one_per_second() {
+		let limiter = RateLimiter::new(0.5);
+
+		assert_eq!(acquire_duration(&limiter).await, Duration::ZERO);
+		assert_eq!(acquire_duration(&limiter).await, Duration::from_secs(2));
+	}
+}
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+
+/// Delay before the first retry, it's doubled for each following attempt
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// When and how long to wait before a failed request is retried
+#[derive(Clone, Debug)]
+pub(crate) struct RetryPolicy {
+	pub(crate) retries: u32,
+	pub(crate) max_wait: Duration,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		RetryPolicy {
+			retries: 0,
+			max_wait: Duration::from_secs(30),
+		}
+	}
+}
+
+impl RetryPolicy {
+	/// 429 means the request wasn't processed, so it can always be retried.
+	/// A 5xx might have been processed, so only idempotent requests are retried, otherwise e.g. a purge job might be created twice.
+	pub(crate) fn should_retry_status(&self, status: StatusCode, idempotent: bool) -> bool {
+		status == StatusCode::TOO_MANY_REQUESTS || (status.is_server_error() && idempotent)
+	}
+
+	/// If the connection couldn't be established, the request never reached the API and can always be retried
+	pub(crate) fn should_retry_transport_error(&self, err: &reqwest::Error, idempotent: bool) -> bool {
+		err.is_connect() || idempotent
+	}
+
+	/// Returns how long to wait before the attempt (starting at 0 for the first retry), or `None` if there shouldn't be another attempt.
+	/// A `Retry-After` header takes precedence over the jittered exponential backoff. If it exceeds the max wait, there's no retry.
+	pub(crate) fn delay(&self, attempt: u32, headers: Option<&HeaderMap>) -> Option<Duration> {
+		if attempt >= self.retries {
+			return None;
+		}
+
+		if let Some(retry_after) = headers.and_then(parse_retry_after) {
+			return if retry_after <= self.max_wait { Some(retry_after) } else { None };
+		}
+
+		let backoff = BASE_DELAY.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_wait);
+		let jittered = rand::thread_rng().gen_range(0..=backoff.as_millis() as u64);
+		Some(Duration::from_millis(jittered))
+	}
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+	let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+	if let Ok(seconds) = value.parse::<u64>() {
+		return Some(Duration::from_secs(seconds));
+	}
+
+	let date = DateTime::parse_from_rfc2822(value).ok()?;
+	let wait = date.with_timezone(&Utc) - Utc::now();
+	Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+	use reqwest::header::HeaderValue;
+
+	use super::*;
+
+	fn policy(retries: u32, max_wait_secs: u64) -> RetryPolicy {
+		RetryPolicy { retries, max_wait: Duration::from_secs(max_wait_secs) }
+	}
+
+	fn retry_after(value: &str) -> HeaderMap {
+		let mut headers = HeaderMap::new();
+		headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+		headers
+	}
+
+	#[test]
+	fn delay_stops_after_the_retries() {
+		assert_eq!(policy(0, 30).delay(0, None), None);
+		assert!(policy(2, 30).delay(1, None).is_some());
+		assert_eq!(policy(2, 30).delay(2, None), None);
+	}
+
+	#[test]
+	fn delay_backs_off_exponentially_up_to_the_max_wait() {
+		let policy = policy(20, 3);
+
+		for _ in 0..100 {
+			assert!(policy.delay(0, None).unwrap() <= BASE_DELAY);
+			assert!(policy.delay(1, None).unwrap() <= BASE_DELAY * 2);
+			assert!(policy.delay(2, None).unwrap() <= BASE_DELAY * 4);
+			assert!(policy.delay(19, None).unwrap() <= Duration::from_secs(3));
+		}
+	}
+
+	#[test]
+	fn delay_uses_retry_after_up_to_the_max_wait() {
+		let policy = policy(3, 30);
+
+		assert_eq!(policy.delay(0, Some(&retry_after("7"))), Some(Duration::from_secs(7)));
+		assert_eq!(policy.delay(0, Some(&retry_after("30"))), Some(Duration::from_secs(30)));
+		assert_eq!(policy.delay(0, Some(&retry_after("31"))), None);
+		assert_eq!(policy.delay(3, Some(&retry_after("1"))), None);
+	}
+
+	#[test]
+	fn parse_retry_after_supports_seconds() {
+		assert_eq!(parse_retry_after(&retry_after("120")), Some(Duration::from_secs(120)));
+		assert_eq!(parse_retry_after(&retry_after(" 0 ")), Some(Duration::ZERO));
+		assert_eq!(parse_retry_after(&retry_after("soon")), None);
+		assert_eq!(parse_retry_after(&HeaderMap::new()), None);
+	}
+
+	#[test]
+	fn parse_retry_after_supports_http_dates() {
+		let in_a_minute = (Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+		let wait = parse_retry_after(&retry_after(&in_a_minute)).unwrap();
+		assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(60), "{:?}", wait);
+
+		// Dates in the past mean no wait
+		assert_eq!(parse_retry_after(&retry_after("Wed, 21 Oct 2015 07:28:00 GMT")), Some(Duration::ZERO));
+	}
+}
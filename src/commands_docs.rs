@@ -0,0 +1,46 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::Command;
+use clap_complete::Shell;
+
+use crate::util::CliError;
+
+pub fn command_completions(mut command: Command, shell: Shell) -> Result<(), CliError> {
+	let name = command.get_name().to_string();
+	clap_complete::generate(shell, &mut command, name, &mut io::stdout());
+	Ok(())
+}
+
+/// Prints the man page of the root command, or writes one page per (sub)command into the dir
+pub fn command_man(command: Command, dir: &Option<PathBuf>) -> Result<(), CliError> {
+	match dir {
+		None => clap_mangen::Man::new(command)
+			.render(&mut io::stdout())
+			.map_err(|err| CliError::InvalidInput(format!("Cannot write man page: {}", err))),
+		Some(dir) => {
+			fs::create_dir_all(dir)
+				.map_err(|err| CliError::InvalidInput(format!("Cannot create dir {}: {}", dir.display(), err)))?;
+			write_man_pages(command, dir)
+		}
+	}
+}
+
+/// Subcommand pages are named like `cdn77-client-jobs-purge.1`, similar to git
+fn write_man_pages(command: Command, dir: &Path) -> Result<(), CliError> {
+	let name = command.get_name().to_string();
+	let file = dir.join(format!("{}.1", name));
+	let mut content = vec![];
+	clap_mangen::Man::new(command.clone())
+		.render(&mut content)
+		.and_then(|_| fs::write(&file, content))
+		.map_err(|err| CliError::InvalidInput(format!("Cannot write man page {}: {}", file.display(), err)))?;
+
+	for subcommand in command.get_subcommands().filter(|s| s.get_name() != "help") {
+		let subcommand_name = format!("{}-{}", name, subcommand.get_name());
+		write_man_pages(subcommand.clone().name(subcommand_name), dir)?;
+	}
+
+	Ok(())
+}
@@ -1,280 +1,1008 @@
 use std::collections::HashMap;
-use std::process;
-use std::str::FromStr;
-
-use reqwest::{Client, StatusCode};
-use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use cdn77::{Cdn77Client, Cdn77Error, ResourceId};
+use cdn77::api_jobs::{JobType, ListJobDetail, MAX_PATHS_PER_JOB, PrefetchRequest, PurgeRequest};
+use chrono::{DateTime, Utc};
+use clap::Args;
+use glob::Pattern;
+use reqwest::Url;
+use serde::Serialize;
+use tokio::sync::Semaphore;
+
+use crate::output::{Output, format_table};
+use crate::path_sources::{PathRewrite, dir_file_to_cdn_paths, dir_files, encode_path, files_to_cdn_paths, git_changed_files, parse_size, sitemap_urls,
+						  url_path, urls_to_paths};
+use crate::resource_resolver::ResourceResolver;
+use crate::util::{CliError, print_dry_run_request};
+
+pub async fn command_jobs_list(client: &Cdn77Client, output: &Output, resource_id: &ResourceId, job_type: &JobType) -> Result<(), CliError> {
+	output.info(format!("Listing jobs of type={} for resource_id={}", job_type, &resource_id));
+	let r = client.list_jobs(*resource_id, *job_type).await?;
+
+	output.render(&r, |r| {
+		println!("Found {} jobs", &r.len());
+
+		for (i, job) in r.iter().enumerate() {
+			println!("\nJob #{}\nID={}\nType={}\nCDN={:?}\nPathsCount={}\nState={}\nQueuedAt={}\nDoneAt={}",
+					 i, job.id, job.resource_type, job.cdn, job.paths_count, job.state, job.queued_at, job.done_at.as_deref().unwrap_or("-"));
+		}
+	});
 
-use crate::{CDN77_API_BASE, EXIT_CODE_API_EXPECTED_ERROR, EXIT_CODE_API_UNEXPECTED_ERROR, EXIT_CODE_INVALID_INPUT, ResourceId};
-use crate::util::{handle_default_response_status_codes, send_http_request_return_response_or_exit};
+	Ok(())
+}
 
-// Docs: https://client.cdn77.com/support/api-reference/v3/jobs
 
-#[derive(Debug)]
-pub enum JobType {
-	Prefetch,
-	Purge,
-	PurgeAll,
+/// A job shown by `jobs watch`, the previous state is only set if it changed since the last refresh
+#[derive(Serialize)]
+struct WatchedJob {
+	resource_id: ResourceId,
+	job_type: String,
+	id: String,
+	state: String,
+	previous_state: Option<String>,
+	/// Until now, or until the job was done
+	queued_for_secs: Option<i64>,
+	paths_count: u64,
+	queued_at: String,
+	done_at: Option<String>,
 }
 
-pub async fn command_jobs_list(client: Client, resource_id: &ResourceId, job_type: &JobType) {
-	let job_type = match job_type {
-		JobType::Prefetch => "prefetch",
-		JobType::Purge => "purge",
-		JobType::PurgeAll => "purge-all",
-	};
-	println!("Listing jobs of type={} for resource_id={}", job_type, &resource_id);
-	let request_url = format!("{}/cdn/{}/job-log/{}", CDN77_API_BASE, &resource_id, job_type);
-	let response = send_http_request_return_response_or_exit(client.get(request_url)).await;
-
-	match response.status() {
-		StatusCode::OK => {
-			match response.json::<Vec<ListJobDetail>>().await {
-				Ok(r) => {
-					println!("Found {} jobs", &r.len());
-
-					for (i, job) in r.into_iter().enumerate() {
-						println!("\nJob #{}\nID={}\nType={}\nCDN={:?}\nPathsCount={}\nState={}\nQueuedAt={}\nDoneAt={}",
-								 i, job.id, job.resource_type, job.cdn, job.paths_count, job.state, job.queued_at, job.done_at);
-					}
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize list-jobs response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-				}
+/// Refreshes a table of the jobs which are queued, or were queued while watching, until interrupted or until all of them are finished.
+/// Jobs which were already done when the watch started are left out, otherwise the whole job history would be shown.
+pub async fn command_jobs_watch(client: &Cdn77Client, output: &Output, resource_ids: &[ResourceId], job_types: &str, interval: u64,
+								until_done: bool) -> Result<(), CliError> {
+	let job_types = parse_job_types(job_types)?;
+	if interval == 0 {
+		return Err(CliError::InvalidInput("The interval must be at least 1 second".to_string()));
+	}
+
+	let started_at = Utc::now();
+	let mut states: HashMap<String, String> = HashMap::new();
+
+	loop {
+		let now = Utc::now();
+		let mut jobs = vec![];
+		for resource_id in resource_ids {
+			for job_type in &job_types {
+				let listed = client.list_jobs(*resource_id, *job_type).await?;
+				jobs.extend(watched_jobs(*resource_id, listed, &states, started_at, now));
 			}
 		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+		jobs.sort_by(|a, b| b.queued_at.cmp(&a.queued_at));
+		states.extend(jobs.iter().map(|j| (j.id.to_string(), j.state.to_string())));
+
+		// Each refresh is one line of JSON, so the output can be consumed while the watch is running
+		output.render_line(&jobs, |jobs| print_watched_jobs(jobs, resource_ids, interval));
+
+		if until_done && is_all_finished(&jobs) {
+			output.info("All watched jobs are finished");
+			return Ok(());
 		}
+		tokio::time::sleep(Duration::from_secs(interval)).await;
 	}
 }
 
-impl FromStr for JobType {
-	type Err = &'static str;
+/// The listed jobs of a resource which are shown, with the state seen in the previous refresh. Jobs which were already finished when the
+/// watch started are left out, unless they were seen before.
+fn watched_jobs(resource_id: ResourceId, listed: Vec<ListJobDetail>, states: &HashMap<String, String>, started_at: DateTime<Utc>,
+				now: DateTime<Utc>) -> Vec<WatchedJob> {
+	listed.into_iter()
+		.filter_map(|job| {
+			let queued_at = DateTime::parse_from_rfc3339(&job.queued_at).ok().map(|q| q.with_timezone(&Utc));
+			let is_new = queued_at.map(|q| q >= started_at).unwrap_or(false);
+			if !states.contains_key(&job.id) && is_finished(&job.state) && !is_new {
+				return None;
+			}
+
+			let done_at = job.done_at.as_deref().and_then(|d| DateTime::parse_from_rfc3339(d).ok());
+			let queued_until = done_at.map(|d| d.with_timezone(&Utc)).unwrap_or(now);
+			Some(WatchedJob {
+				resource_id,
+				job_type: job.resource_type,
+				previous_state: states.get(&job.id).filter(|s| **s != job.state).cloned(),
+				queued_for_secs: queued_at.map(|q| (queued_until - q).num_seconds().max(0)),
+				id: job.id,
+				state: job.state,
+				paths_count: job.paths_count,
+				queued_at: job.queued_at,
+				done_at: job.done_at,
+			})
+		})
+		.collect()
+}
+
+/// Redraws the screen if stdout is a terminal, jobs whose state changed since the last refresh are highlighted
+fn print_watched_jobs(jobs: &[WatchedJob], resource_ids: &[ResourceId], interval: u64) {
+	let is_terminal = io::stdout().is_terminal();
+	if is_terminal {
+		print!("\x1b[2J\x1b[H");
+	}
+	println!("{} Watching jobs of resource_ids={:?} every {}s\n", Utc::now().format("%Y-%m-%d %H:%M:%S"), resource_ids, interval);
+	if jobs.is_empty() {
+		println!("No queued jobs");
+		return;
+	}
+	watched_jobs_table(jobs, is_terminal).iter().for_each(|line| println!("{}", line));
+}
 
-	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		match s {
-			"prefetch" => Ok(JobType::Prefetch),
-			"purge" => Ok(JobType::Purge),
-			"purge-all" => Ok(JobType::PurgeAll),
-			_ => Err("Invalid job type"),
+/// The lines of the job table, the state column shows changes as `queued -> done` and `highlight` also colors the rows of changed jobs
+fn watched_jobs_table(jobs: &[WatchedJob], highlight: bool) -> Vec<String> {
+	let columns: Vec<String> = ["resource_id", "type", "id", "state", "queued_for", "paths_count", "queued_at"].iter().map(|c| c.to_string()).collect();
+	let rows: Vec<Vec<String>> = jobs.iter()
+		.map(|j| vec![
+			j.resource_id.to_string(),
+			j.job_type.to_string(),
+			j.id.to_string(),
+			match &j.previous_state {
+				Some(previous) => format!("{} -> {}", previous, j.state),
+				None => j.state.to_string(),
+			},
+			j.queued_for_secs.map(format_duration).unwrap_or_else(|| "-".to_string()),
+			j.paths_count.to_string(),
+			j.queued_at.to_string(),
+		])
+		.collect();
+
+	let mut lines = format_table(&columns, &rows);
+	for (line, job) in lines.iter_mut().skip(2).zip(jobs) {
+		if job.previous_state.is_some() && highlight {
+			*line = format!("\x1b[1;33m{}\x1b[0m", line);
 		}
 	}
+	lines
 }
 
-#[derive(Debug, Deserialize)]
-struct ListJobDetail {
-	id: String,
-	#[serde(rename = "type")]
-	resource_type: String,
-	cdn: HashMap<String, ResourceId>,
-	paths_count: u64,
-	state: String,
-	queued_at: String,
-	done_at: String,
+/// Whether `--until-done` stops the watch, which is also the case if no job is watched at all
+fn is_all_finished(jobs: &[WatchedJob]) -> bool {
+	jobs.iter().all(|j| is_finished(&j.state))
 }
 
+/// Jobs don't change anymore in these states
+fn is_finished(state: &str) -> bool {
+	state == "done" || state == "failed"
+}
 
-pub async fn command_jobs_detail(client: Client, resource_id: &ResourceId, job_id: &str) {
-	println!("Getting job details for job_id={} in resource_id={}", job_id, resource_id);
-	let request_url = format!("{}/cdn/{}/job/{}", CDN77_API_BASE, resource_id, job_id);
-	let response = send_http_request_return_response_or_exit(client.get(request_url)).await;
+/// Like `1h02m03s`, leading zero units are left out
+fn format_duration(secs: i64) -> String {
+	match (secs / 3600, secs % 3600 / 60, secs % 60) {
+		(0, 0, s) => format!("{}s", s),
+		(0, m, s) => format!("{}m{:02}s", m, s),
+		(h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+	}
+}
 
-	match response.status() {
-		StatusCode::OK => {
-			match response.json::<GetJobDetailsResponse>().await {
-				Ok(r) => {
-					println!("Found Job\nID={}\nType={}\nCDN={:?}\nPaths={:?}\nPathsCount={}\nState={}\nQueuedAt={}\nDoneAt={}",
-							 r.id, r.resource_type, r.cdn, r.paths, r.paths_count, r.state, r.queued_at, r.done_at);
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize job-details response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-				}
-			}
+fn parse_job_types(job_types: &str) -> Result<Vec<JobType>, CliError> {
+	job_types.split(',')
+		.map(|t| t.trim())
+		.filter(|t| !t.is_empty())
+		.map(|t| t.parse().map_err(|err: &str| CliError::InvalidInput(format!("{}: {}", err, t))))
+		.collect()
+}
+
+
+pub async fn command_jobs_detail(client: &Cdn77Client, output: &Output, resource_id: &ResourceId, job_id: &str) -> Result<(), CliError> {
+	output.info(format!("Getting job details for job_id={} in resource_id={}", job_id, resource_id));
+
+	match client.job_detail(*resource_id, job_id).await {
+		Ok(r) => {
+			output.render(&r, |r| {
+				println!("Found Job\nID={}\nType={}\nCDN={:?}\nPaths={:?}\nPathsCount={}\nState={}\nQueuedAt={}\nDoneAt={}",
+						 r.id, r.resource_type, r.cdn, r.paths, r.paths_count, r.state, r.queued_at, r.done_at.as_deref().unwrap_or("-"));
+			});
+			Ok(())
 		}
-		StatusCode::NOT_FOUND => {
-			println!("Didn't find job_id={} for resource_id={}", job_id, resource_id);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
+		Err(err @ Cdn77Error::NotFound { .. }) => {
+			Err(CliError::api_with_message(err, format!("Didn't find job_id={} for resource_id={}", job_id, resource_id)))
+		}
+		Err(err) => Err(err.into()),
+	}
+}
+
+
+pub async fn command_jobs_prefetch(client: &Cdn77Client, output: &Output, resource_id: &ResourceId, paths: &PathsArgs,
+								   upstream_host: &Option<String>, batch: &BatchArgs, wait: &WaitArgs) -> Result<(), CliError> {
+	prefetch_paths(client, output, resource_id, paths.read()?, upstream_host, batch, wait).await
+}
+
+/// Purges full URLs, possibly on multiple hosts, on the resources which own the hosts as CDN URL or CNAME.
+/// Nothing is purged if a host isn't owned by any resource of the account.
+pub async fn command_jobs_purge_urls(client: &Cdn77Client, output: &Output, resolver: &ResourceResolver<'_>, urls: &[String], batch: &BatchArgs,
+									 wait: &WaitArgs) -> Result<(), CliError> {
+	batch.validate()?;
+	wait.validate()?;
+	let urls = urls.iter()
+		.flat_map(|u| split_urls(u))
+		.map(|u| Url::parse(&u).map_err(|err| CliError::InvalidInput(format!("Invalid URL {}: {}", u, err))))
+		.collect::<Result<Vec<Url>, CliError>>()?;
+	if urls.is_empty() {
+		return Err(CliError::InvalidInput("Please specify at least one URL".to_string()));
+	}
+
+	let mut owners: HashMap<String, Option<ResourceId>> = HashMap::new();
+	for url in &urls {
+		let host = url_host(url)?;
+		if !owners.contains_key(host) {
+			owners.insert(host.to_string(), resolver.owner_of_host(host).await?);
 		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+		match owners[host] {
+			Some(resource_id) => output.info(format!("{} -> resource_id={} path={}", url, resource_id, url_path(url))),
+			None => output.info(format!("{} -> no resource owns the host", url)),
 		}
 	}
+
+	let paths_by_resource = group_paths_by_owner(&urls, &owners)?;
+	submit_batches(client, output, PathsJob::Purge, paths_by_resource, batch, wait).await
 }
 
-#[derive(Debug, Deserialize)]
-struct GetJobDetailsResponse {
-	id: String,
-	#[serde(rename = "type")]
-	resource_type: String,
-	cdn: HashMap<String, ResourceId>,
-	paths: Vec<String>,
-	paths_count: u64,
-	state: String,
-	queued_at: String,
-	done_at: String,
+/// Splits a comma separated list of URLs. A comma only separates two URLs if it's followed by http:// or https://, so commas in e.g. the
+/// query string are kept.
+fn split_urls(urls: &str) -> Vec<String> {
+	let mut split: Vec<String> = vec![];
+	for part in urls.split(',') {
+		let starts_url = part.trim_start().starts_with("http://") || part.trim_start().starts_with("https://");
+		match split.last_mut() {
+			Some(last) if !starts_url => {
+				last.push(',');
+				last.push_str(part);
+			}
+			_ => split.push(part.to_string()),
+		}
+	}
+
+	split.into_iter()
+		.map(|u| u.trim().to_string())
+		.filter(|u| !u.is_empty())
+		.collect()
 }
 
+fn url_host(url: &Url) -> Result<&str, CliError> {
+	url.host_str().ok_or_else(|| CliError::InvalidInput(format!("The URL {} has no host", url)))
+}
 
-pub async fn command_jobs_prefetch(client: Client, resource_id: &ResourceId, paths: &str, upstream_host: &Option<String>) {
-	let paths: Vec<String> = paths.split(',')
-		.filter(|s| !s.trim().is_empty())
-		.map(|s| s.to_string()).collect();
+/// The deduplicated paths of the URLs per owning resource, in the order of the URLs. Fails if any host has no owner.
+fn group_paths_by_owner(urls: &[Url], owners: &HashMap<String, Option<ResourceId>>) -> Result<Vec<(ResourceId, Vec<String>)>, CliError> {
+	let mut paths_by_resource: Vec<(ResourceId, Vec<String>)> = vec![];
+	let mut unowned_hosts: Vec<&str> = vec![];
+
+	for url in urls {
+		let host = url_host(url)?;
+		let path = url_path(url);
+		match owners.get(host).copied().flatten() {
+			Some(resource_id) => match paths_by_resource.iter_mut().find(|(id, _)| *id == resource_id) {
+				Some((_, paths)) if paths.contains(&path) => {}
+				Some((_, paths)) => paths.push(path),
+				None => paths_by_resource.push((resource_id, vec![path])),
+			},
+			None if !unowned_hosts.contains(&host) => unowned_hosts.push(host),
+			None => {}
+		}
+	}
 
-	if paths.is_empty() {
-		eprintln!("Please specify at least one path");
-		process::exit(EXIT_CODE_INVALID_INPUT);
+	if !unowned_hosts.is_empty() {
+		return Err(CliError::InvalidInput(format!("No CDN resource owns the hosts {}, nothing was purged", unowned_hosts.join(", "))));
 	}
 
-	println!("Prefetching paths={:?} from resource_id={}", &paths, resource_id);
-	let request_url = format!("{}/cdn/{}/job/prefetch", CDN77_API_BASE, resource_id);
+	Ok(paths_by_resource)
+}
+
+/// Prefetches paths from any source, split into batches if needed
+async fn prefetch_paths(client: &Cdn77Client, output: &Output, resource_id: &ResourceId, paths: Vec<String>, upstream_host: &Option<String>,
+						batch: &BatchArgs, wait: &WaitArgs) -> Result<(), CliError> {
+	batch.validate()?;
+	wait.validate()?;
+	if paths.len() > batch.batch_size {
+		return submit_batches(client, output, PathsJob::Prefetch { upstream_host: upstream_host.clone() }, vec![(*resource_id, paths)], batch, wait).await;
+	}
+
+	output.info(format!("Prefetching paths={:?} from resource_id={}", &paths, resource_id));
 	let request = PrefetchRequest {
 		paths,
 		upstream_host: upstream_host.clone(),
 	};
-	let response = send_http_request_return_response_or_exit(client.post(request_url).json(&request)).await;
-
-	match response.status() {
-		StatusCode::ACCEPTED => {
-			match response.json::<PrefetchResponse>().await {
-				Ok(r) => {
-					println!("Successfully executed {} of resource_ids={:?}\nJobID={}\nPaths={}/{:?}\nState={}\nQueuedAt={}",
-							 r.resource_type, r.cdn, r.id, r.paths_count, r.paths, r.state, r.queued_at);
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize prefetch response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
+
+	match client.prefetch(*resource_id, &request).await {
+		Ok(r) => {
+			output.render(&r, |r| {
+				println!("Successfully executed {} of resource_ids={:?}\nJobID={}\nPaths={}/{:?}\nState={}\nQueuedAt={}",
+						 r.resource_type, r.cdn, r.id, r.paths_count, r.paths, r.state, r.queued_at);
+			});
+			wait.wait_for_jobs(client, &[(*resource_id, r.id)]).await
+		}
+		Err(err @ Cdn77Error::NotFound { .. }) => {
+			Err(CliError::api_with_message(err, format!("Cannot prefetch paths, didn't find resource_id={}", resource_id)))
+		}
+		Err(err) => Err(err.into()),
+	}
+}
+
+
+pub async fn command_jobs_purge(client: &Cdn77Client, output: &Output, resource_id: &ResourceId, paths: &PathsArgs, batch: &BatchArgs,
+								wait: &WaitArgs) -> Result<(), CliError> {
+	purge_paths(client, output, resource_id, paths.read()?, batch, wait).await
+}
+
+/// Purges paths from any source, split into batches if needed
+async fn purge_paths(client: &Cdn77Client, output: &Output, resource_id: &ResourceId, paths: Vec<String>, batch: &BatchArgs,
+					 wait: &WaitArgs) -> Result<(), CliError> {
+	batch.validate()?;
+	wait.validate()?;
+	if paths.len() > batch.batch_size {
+		return submit_batches(client, output, PathsJob::Purge, vec![(*resource_id, paths)], batch, wait).await;
+	}
+
+	output.info(format!("Purging paths={:?} from resource_id={}", &paths, resource_id));
+	let request = PurgeRequest {
+		paths,
+	};
+
+	match client.purge(*resource_id, &request).await {
+		Ok(r) => {
+			output.render(&r, |r| {
+				println!("Successfully executed {} of resource_ids={:?}\nJobID={}\nPaths={}/{:?}\nState={}\nQueuedAt={}",
+						 r.resource_type, r.cdn, r.id, r.paths_count, r.paths, r.state, r.queued_at);
+			});
+			wait.wait_for_jobs(client, &[(*resource_id, r.id)]).await
+		}
+		Err(err @ Cdn77Error::NotFound { .. }) => {
+			Err(CliError::api_with_message(err, format!("Cannot purge paths, didn't find resource_id={}", resource_id)))
+		}
+		Err(err) => Err(err.into()),
+	}
+}
+
+
+/// Splitting of long path lists into multiple purge/prefetch jobs
+#[derive(Debug, Args)]
+pub struct BatchArgs {
+	#[clap(long, default_value_t = MAX_PATHS_PER_JOB)]
+	/// Maximum paths per job, longer lists are submitted as multiple jobs. The API allows up to 2000.
+	batch_size: usize,
+	#[clap(long, default_value = "1")]
+	/// How many jobs are submitted at the same time if the paths are split
+	concurrency: usize,
+}
+
+impl BatchArgs {
+	fn validate(&self) -> Result<(), CliError> {
+		if self.batch_size == 0 || self.batch_size > MAX_PATHS_PER_JOB {
+			return Err(CliError::InvalidInput(format!("The batch size must be between 1 and {}", MAX_PATHS_PER_JOB)));
+		}
+		if self.concurrency == 0 {
+			return Err(CliError::InvalidInput("The concurrency must be at least 1".to_string()));
+		}
+		Ok(())
+	}
+}
+
+/// Blocking until the created jobs are finished, e.g. so CI runs smoke tests only after the purge
+#[derive(Debug, Args)]
+pub struct WaitArgs {
+	#[clap(long)]
+	/// Wait until the created jobs are done, progress is printed to stderr
+	wait: bool,
+	#[clap(long, default_value = "600", requires = "wait")]
+	/// Seconds to wait for the jobs before giving up with exit code 5
+	wait_timeout: u64,
+	#[clap(long, default_value = "5", requires = "wait")]
+	/// Seconds between two polls of the job state
+	poll_interval: u64,
+}
+
+impl WaitArgs {
+	fn validate(&self) -> Result<(), CliError> {
+		match self.poll_interval {
+			0 => Err(CliError::InvalidInput("The poll interval must be at least 1 second".to_string())),
+			_ => Ok(()),
+		}
+	}
+
+	/// Polls the job details until all jobs are done. Progress goes to stderr, so stdout only holds the result of the command.
+	async fn wait_for_jobs(&self, client: &Cdn77Client, jobs: &[(ResourceId, String)]) -> Result<(), CliError> {
+		if !self.wait || jobs.is_empty() {
+			return Ok(());
+		}
+
+		let started = Instant::now();
+		let timeout = Duration::from_secs(self.wait_timeout);
+		let mut pending: Vec<&(ResourceId, String)> = jobs.iter().collect();
+
+		loop {
+			let mut still_pending = vec![];
+			for job in pending {
+				let (resource_id, job_id) = job;
+				match client.job_detail(*resource_id, job_id).await?.state.as_str() {
+					"done" => eprintln!("Job {} is done", job_id),
+					"failed" => return Err(CliError::JobFailed(format!("Job {} of resource_id={} failed", job_id, resource_id))),
+					_ => still_pending.push(job),
 				}
 			}
+			pending = still_pending;
+
+			let elapsed = started.elapsed();
+			if pending.is_empty() {
+				eprintln!("All {} jobs are done after {}s", jobs.len(), elapsed.as_secs());
+				return Ok(());
+			}
+			if elapsed >= timeout {
+				let pending: Vec<String> = pending.iter().map(|(resource_id, job_id)| format!("{} of resource_id={}", job_id, resource_id)).collect();
+				return Err(CliError::WaitTimeout(format!("Timed out after {}s waiting for the jobs {}", self.wait_timeout, pending.join(", "))));
+			}
+
+			eprintln!("Waiting for {} of {} jobs ({}s elapsed)", pending.len(), jobs.len(), elapsed.as_secs());
+			tokio::time::sleep(Duration::from_secs(self.poll_interval).min(timeout - elapsed)).await;
 		}
-		StatusCode::NOT_FOUND => {
-			println!("Cannot prefetch paths, didn't find resource_id={}", resource_id);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
-		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+	}
+}
+
+/// Purge and prefetch jobs are batched the same way, only the request differs
+#[derive(Clone)]
+enum PathsJob {
+	Prefetch { upstream_host: Option<String> },
+	Purge,
+}
+
+impl PathsJob {
+	/// Returns the ID of the created job
+	async fn submit(&self, client: &Cdn77Client, resource_id: ResourceId, paths: Vec<String>) -> Result<String, Cdn77Error> {
+		match self {
+			PathsJob::Prefetch { upstream_host } => {
+				let request = PrefetchRequest { paths, upstream_host: upstream_host.clone() };
+				client.prefetch(resource_id, &request).await.map(|r| r.id)
+			}
+			PathsJob::Purge => client.purge(resource_id, &PurgeRequest { paths }).await.map(|r| r.id),
 		}
 	}
 }
 
+/// The outcome of one batch, exactly one of job ID and error is set
 #[derive(Serialize)]
-struct PrefetchRequest {
+struct BatchResult {
+	batch: usize,
+	resource_id: ResourceId,
+	job_id: Option<String>,
+	error: Option<String>,
+	paths_count: usize,
 	paths: Vec<String>,
-	upstream_host: Option<String>,
 }
 
-#[derive(Deserialize)]
-struct PrefetchResponse {
-	id: String,
-	#[serde(rename = "type")]
-	resource_type: String,
-	cdn: HashMap<String, ResourceId>,
-	paths: Vec<String>,
-	paths_count: u64,
-	state: String,
-	queued_at: String,
+/// Submits one job per batch of the paths of each resource, with at most `concurrency` requests in flight. A failed batch doesn't stop the
+/// others, the result lists the paths of every batch so failed ones can be resubmitted.
+async fn submit_batches(client: &Cdn77Client, output: &Output, job: PathsJob, paths_by_resource: Vec<(ResourceId, Vec<String>)>, batch: &BatchArgs,
+						wait: &WaitArgs) -> Result<(), CliError> {
+	let paths_count: usize = paths_by_resource.iter().map(|(_, paths)| paths.len()).sum();
+	let resource_ids: Vec<ResourceId> = paths_by_resource.iter().map(|(resource_id, _)| *resource_id).collect();
+	let batches: Vec<(ResourceId, Vec<String>)> = paths_by_resource.iter()
+		.flat_map(|(resource_id, paths)| paths.chunks(batch.batch_size).map(|c| (*resource_id, c.to_vec())))
+		.collect();
+	output.info(format!("Submitting {} paths as {} jobs of up to {} paths to resource_ids={:?}", paths_count, batches.len(), batch.batch_size, resource_ids));
+
+	let semaphore = Arc::new(Semaphore::new(batch.concurrency));
+	let tasks: Vec<_> = batches.iter()
+		.cloned()
+		.map(|(resource_id, paths)| {
+			let (client, job, semaphore) = (client.clone(), job.clone(), semaphore.clone());
+			tokio::spawn(async move {
+				let _permit = semaphore.acquire_owned().await.expect("The semaphore is never closed");
+				job.submit(&client, resource_id, paths).await
+			})
+		})
+		.collect();
+
+	let mut results = vec![];
+	let mut errors = vec![];
+	let mut dry_run_requests = vec![];
+	for (i, (task, (resource_id, paths))) in tasks.into_iter().zip(batches).enumerate() {
+		let (job_id, error) = match task.await.expect("Submitting a batch doesn't panic") {
+			Ok(job_id) => (Some(job_id), None),
+			Err(Cdn77Error::DryRun(request)) => {
+				dry_run_requests.push(request);
+				continue;
+			}
+			Err(err) => {
+				let message = err.to_string();
+				errors.push(err);
+				(None, Some(message))
+			}
+		};
+		results.push(BatchResult { batch: i + 1, resource_id, job_id, error, paths_count: paths.len(), paths });
+	}
+
+	if !dry_run_requests.is_empty() {
+		output.render(&dry_run_requests, |requests| requests.iter().for_each(print_dry_run_request));
+		return Ok(());
+	}
+
+	output.render(&results, |results| {
+		for r in results {
+			match (&r.job_id, &r.error) {
+				(Some(job_id), _) => println!("Batch #{}: ResourceID={} JobID={} PathsCount={}", r.batch, r.resource_id, job_id, r.paths_count),
+				(None, error) => println!("Batch #{}: ResourceID={} FAILED PathsCount={} Error={}\nPaths={:?}",
+										  r.batch, r.resource_id, r.paths_count, error.as_deref().unwrap_or("-"), r.paths),
+			}
+		}
+		let job_ids: Vec<&str> = results.iter().filter_map(|r| r.job_id.as_deref()).collect();
+		println!("\nCreated {} of {} jobs\nJobIDs={}", job_ids.len(), results.len(), job_ids.join(","));
+	});
+
+	match errors.into_iter().next() {
+		None => {
+			let jobs: Vec<(ResourceId, String)> = results.into_iter().filter_map(|r| r.job_id.map(|job_id| (r.resource_id, job_id))).collect();
+			wait.wait_for_jobs(client, &jobs).await
+		}
+		Some(err) => {
+			let failed = results.iter().filter(|r| r.error.is_some()).count();
+			let message = format!("{} of {} batches failed, first error: {}", failed, results.len(), err);
+			Err(CliError::api_with_message(err, message))
+		}
+	}
 }
 
 
-pub async fn command_jobs_purge(client: Client, resource_id: &ResourceId, paths: &str) {
-	let paths: Vec<String> = paths.split(',')
-		.filter(|s| !s.trim().is_empty())
-		.map(|s| s.to_string()).collect();
+/// The git revisions and how the changed files map to CDN paths
+#[derive(Debug, Args)]
+pub struct GitDiffArgs {
+	#[clap(long)]
+	/// The revision which is currently deployed, e.g. a tag or commit hash
+	from: String,
+	#[clap(long, default_value = "HEAD")]
+	/// The revision which is being deployed
+	to: String,
+	#[clap(long, default_value = ".")]
+	/// Path of the local git repository
+	repo: PathBuf,
+	#[clap(long, default_value = "")]
+	/// (opt) Dir of the repository which is served as the root of the resource, e.g. `public`. Files outside of it are ignored.
+	web_root: String,
+	#[clap(long = "rewrite")]
+	/// (opt) Replace a path prefix as FROM=TO, e.g. `/static/=/assets/`, can be repeated. The first matching rule is applied.
+	rewrites: Vec<PathRewrite>,
+}
+
+/// Purges the changed and deleted files between two revisions. With `dry_run`, the computed paths are printed instead.
+pub async fn command_jobs_purge_git(client: &Cdn77Client, output: &Output, resource_id: &ResourceId, git: &GitDiffArgs, batch: &BatchArgs,
+									wait: &WaitArgs, dry_run: bool) -> Result<(), CliError> {
+	let files = git_changed_files(&git.repo, &git.from, &git.to)?;
+	let paths = files_to_cdn_paths(&files, &git.web_root, &git.rewrites);
+	output.info(format!("{} files changed between {} and {}, {} of them map to CDN paths", files.len(), git.from, git.to, paths.len()));
+	// Most likely a mistyped web root, which would make a deploy purge nothing
+	if !files.is_empty() && paths.is_empty() {
+		return Err(CliError::InvalidInput(format!("None of the {} changed files is below the web root '{}'", files.len(), git.web_root)));
+	}
 
+	if dry_run {
+		output.render(&paths, |paths| paths.iter().for_each(|p| println!("{}", p)));
+		return Ok(());
+	}
 	if paths.is_empty() {
-		eprintln!("Please specify at least one path");
-		process::exit(EXIT_CODE_INVALID_INPUT);
+		output.info("Nothing to purge");
+		return Ok(());
 	}
 
-	println!("Purging paths={:?} from resource_id={}", &paths, resource_id);
-	let request_url = format!("{}/cdn/{}/job/purge", CDN77_API_BASE, resource_id);
-	let request = PurgeRequest {
-		paths,
+	purge_paths(client, output, resource_id, paths, batch, wait).await
+}
+
+
+/// Include/exclude globs matched against the CDN paths, `*` matches `/` as well
+#[derive(Debug, Args)]
+pub struct GlobFilterArgs {
+	#[clap(long = "include")]
+	/// (opt) Only use paths matching this glob, e.g. `/blog/*`, can be repeated
+	includes: Vec<Pattern>,
+	#[clap(long = "exclude")]
+	/// (opt) Skip paths matching this glob, e.g. `*.pdf`, can be repeated
+	excludes: Vec<Pattern>,
+}
+
+impl GlobFilterArgs {
+	/// Without includes, all paths which aren't excluded match
+	fn matches(&self, path: &str) -> bool {
+		(self.includes.is_empty() || self.includes.iter().any(|p| p.matches(path))) && !self.excludes.iter().any(|p| p.matches(path))
+	}
+}
+
+/// A local sitemap and which of its URLs to prefetch
+#[derive(Debug, Args)]
+pub struct SitemapArgs {
+	#[clap(short = 'f', long)]
+	/// The sitemap.xml or sitemap index, nested sitemaps must be local files in the same dir
+	file: PathBuf,
+	#[clap(long)]
+	/// Prefetch the URLs on hosts which aren't the CDN URL or a CNAME of the resource as well, e.g. of the origin
+	any_host: bool,
+	#[clap(flatten)]
+	filter: GlobFilterArgs,
+}
+
+/// Prefetches the paths of the URLs of a sitemap. URLs on hosts of other or no resources are skipped unless `--any-host` is given.
+#[allow(clippy::too_many_arguments)]
+pub async fn command_jobs_prefetch_sitemap(client: &Cdn77Client, output: &Output, resolver: &ResourceResolver<'_>, resource_id: &ResourceId,
+										   sitemap: &SitemapArgs, upstream_host: &Option<String>, batch: &BatchArgs, wait: &WaitArgs) -> Result<(), CliError> {
+	let urls = sitemap_urls(&sitemap.file)?;
+	let served_urls = match sitemap.any_host {
+		true => urls.clone(),
+		false => urls_on_resource(output, resolver, resource_id, &urls).await?,
 	};
-	let response = send_http_request_return_response_or_exit(client.post(request_url).json(&request)).await;
-
-	match response.status() {
-		StatusCode::ACCEPTED => {
-			match response.json::<PurgeResponse>().await {
-				Ok(r) => {
-					println!("Successfully executed {} of resource_ids={:?}\nJobID={}\nPaths={}/{:?}\nState={}\nQueuedAt={}",
-							 r.resource_type, r.cdn, r.id, r.paths_count, r.paths, r.state, r.queued_at);
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize purge response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-				}
+	let paths: Vec<String> = urls_to_paths(&served_urls)?.into_iter().filter(|p| sitemap.filter.matches(p)).collect();
+	output.info(format!("Found {} URLs in {}, {} of them match the hosts and filters", urls.len(), sitemap.file.display(), paths.len()));
+
+	if paths.is_empty() {
+		output.info("Nothing to prefetch");
+		return Ok(());
+	}
+
+	prefetch_paths(client, output, resource_id, paths, upstream_host, batch, wait).await
+}
+
+
+/// The URLs whose host is the CDN URL or a CNAME of the resource, the others are skipped with a warning per host
+async fn urls_on_resource(output: &Output, resolver: &ResourceResolver<'_>, resource_id: &ResourceId, urls: &[String]) -> Result<Vec<String>, CliError> {
+	let mut owners: HashMap<String, Option<ResourceId>> = HashMap::new();
+	let mut served_urls = vec![];
+
+	for url in urls {
+		let parsed = Url::parse(url).map_err(|err| CliError::InvalidInput(format!("Invalid URL {}: {}", url, err)))?;
+		let host = url_host(&parsed)?;
+		if !owners.contains_key(host) {
+			let owner = resolver.owner_of_host(host).await?;
+			match owner {
+				Some(owner) if owner == *resource_id => {}
+				Some(owner) => output.info(format!("Skipping the URLs on {}, it belongs to resource_id={}, use --any-host to prefetch them anyway", host, owner)),
+				None => output.info(format!("Skipping the URLs on {}, no resource owns it, use --any-host to prefetch them anyway", host)),
 			}
+			owners.insert(host.to_string(), owner);
 		}
-		StatusCode::NOT_FOUND => {
-			println!("Cannot purge paths, didn't find resource_id={}", resource_id);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
-		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+		if owners[host] == Some(*resource_id) {
+			served_urls.push(url.to_string());
 		}
 	}
+
+	Ok(served_urls)
 }
 
-#[derive(Serialize)]
-struct PurgeRequest {
-	paths: Vec<String>,
+
+/// A local dir, e.g. the build output, and which of its files to use
+#[derive(Debug, Args)]
+pub struct DirArgs {
+	/// The dir to walk, e.g. `./dist`
+	dir: PathBuf,
+	#[clap(long, default_value = "/")]
+	/// Path under which the dir is served, e.g. `/static`. A missing leading / is added.
+	url_prefix: String,
+	#[clap(long, parse(try_from_str = parse_size))]
+	/// (opt) Skip files larger than this, e.g. 10M. K, M and G are supported.
+	max_size: Option<u64>,
+	#[clap(flatten)]
+	filter: GlobFilterArgs,
 }
 
-#[derive(Deserialize)]
-struct PurgeResponse {
-	id: String,
-	#[serde(rename = "type")]
-	resource_type: String,
-	cdn: HashMap<String, ResourceId>,
-	paths: Vec<String>,
-	paths_count: u64,
-	state: String,
-	queued_at: String,
+impl DirArgs {
+	/// The encoded CDN paths of the matching files, globs are matched against the unencoded paths.
+	/// With `with_index_files`, an `index.html` is returned as its dir and as the file itself, e.g. to purge both cached URLs.
+	fn paths(&self, output: &Output, with_index_files: bool) -> Result<Vec<String>, CliError> {
+		let files = dir_files(&self.dir)?;
+		let paths: Vec<String> = files.iter()
+			.filter(|f| self.max_size.map(|max| f.size <= max).unwrap_or(true))
+			.flat_map(|f| dir_file_to_cdn_paths(&self.url_prefix, &f.relative_path, with_index_files))
+			.filter(|p| self.filter.matches(p))
+			.map(|p| encode_path(&p))
+			.collect();
+
+		output.info(format!("Found {} files in {}, {} CDN paths of them match the filters", files.len(), self.dir.display(), paths.len()));
+		Ok(paths)
+	}
 }
 
+/// Prefetches the files of a local dir
+pub async fn command_jobs_prefetch_dir(client: &Cdn77Client, output: &Output, resource_id: &ResourceId, dir: &DirArgs,
+									   upstream_host: &Option<String>, batch: &BatchArgs, wait: &WaitArgs) -> Result<(), CliError> {
+	let paths = dir.paths(output, false)?;
+	if paths.is_empty() {
+		output.info("Nothing to prefetch");
+		return Ok(());
+	}
+
+	prefetch_paths(client, output, resource_id, paths, upstream_host, batch, wait).await
+}
 
-pub async fn command_jobs_purge_all(client: Client, resource_id: &ResourceId) {
-	println!("Purging all data in resource_id={}", &resource_id);
-	let request_url = format!("{}/cdn/{}/job/purge-all", CDN77_API_BASE, &resource_id);
-	let response = send_http_request_return_response_or_exit(client.post(request_url)).await;
+/// Purges the files of a local dir, an `index.html` is purged as its dir and as the file, as either URL might be cached
+pub async fn command_jobs_purge_dir(client: &Cdn77Client, output: &Output, resource_id: &ResourceId, dir: &DirArgs, batch: &BatchArgs,
+									wait: &WaitArgs) -> Result<(), CliError> {
+	let paths = dir.paths(output, true)?;
+	if paths.is_empty() {
+		output.info("Nothing to purge");
+		return Ok(());
+	}
 
-	match response.status() {
-		StatusCode::ACCEPTED => {
-			match response.json::<PurgeAllResponse>().await {
-				Ok(r) => {
-					println!("Successfully executed {} of resource IDs {:?}\nJobID={}\nType={}\nState={}\nQueuedAt={}\nDoneAt={}",
-							 r.resource_type, r.cdn, r.id, r.resource_type, r.state, r.queued_at, r.done_at);
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize purge-all response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-				}
-			}
+	purge_paths(client, output, resource_id, paths, batch, wait).await
+}
+
+
+/// Protected resources are refused unless forced. Without `confirm`, e.g. because of `--yes`, there's no interactive prompt.
+pub async fn command_jobs_purge_all(client: &Cdn77Client, output: &Output, resource_id: &ResourceId, protected_resource_ids: &[ResourceId],
+									force_protected: bool, confirm: bool, wait: &WaitArgs) -> Result<(), CliError> {
+	if protected_resource_ids.contains(resource_id) && !force_protected {
+		return Err(CliError::InvalidInput(format!("resource_id={} is protected in the config, use --force-protected to purge all files anyway", resource_id)));
+	}
+	wait.validate()?;
+	if confirm {
+		confirm_purge_all(client, resource_id).await?;
+	}
+
+	output.info(format!("Purging all data in resource_id={}", &resource_id));
+
+	match client.purge_all(*resource_id).await {
+		Ok(r) => {
+			output.render(&r, |r| {
+				println!("Successfully executed {} of resource IDs {:?}\nJobID={}\nType={}\nState={}\nQueuedAt={}\nDoneAt={}",
+						 r.resource_type, r.cdn, r.id, r.resource_type, r.state, r.queued_at, r.done_at.as_deref().unwrap_or("-"));
+			});
+			wait.wait_for_jobs(client, &[(*resource_id, r.id)]).await
 		}
-		StatusCode::FORBIDDEN => {
-			println!("Purging all files is disabled for resource={}: {:?}", resource_id, response);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
+		Err(Cdn77Error::Forbidden { url, body }) => {
+			let message = format!("Purging all files is disabled for resource={}: {}", resource_id, body);
+			Err(CliError::api_with_message(Cdn77Error::Forbidden { url, body }, message))
 		}
-		StatusCode::NOT_FOUND => {
-			eprintln!("Didn't find resource_id={}", resource_id);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
+		Err(err @ Cdn77Error::NotFound { .. }) => {
+			Err(CliError::api_with_message(err, format!("Didn't find resource_id={}", resource_id)))
 		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+		Err(err) => Err(err.into()),
+	}
+}
+
+/// Shows the resource's hostnames, so a mistyped resource ID stands out, and asks on stderr to keep stdout clean
+async fn confirm_purge_all(client: &Cdn77Client, resource_id: &ResourceId) -> Result<(), CliError> {
+	if !io::stdin().is_terminal() {
+		return Err(CliError::InvalidInput("Purge-all must be confirmed, use --yes when running non-interactively".to_string()));
+	}
+
+	let resource = match client.resource_detail(*resource_id).await {
+		Ok(r) => r,
+		Err(err @ Cdn77Error::NotFound { .. }) => {
+			return Err(CliError::api_with_message(err, format!("Didn't find resource_id={}", resource_id)));
+		}
+		Err(err) => return Err(err.into()),
+	};
+
+	eprintln!("About to purge ALL files of resource_id={} label={}\nCDN URL={}\nCNAMEs={}",
+			  resource.id, resource.label.as_deref().unwrap_or("-"), resource.cdn_url, resource.cnames.join(", "));
+	eprint!("Continue? [y/N] ");
+
+	let mut answer = String::new();
+	io::stdin().read_line(&mut answer)
+		.map_err(|err| CliError::InvalidInput(format!("Cannot read confirmation: {}", err)))?;
+
+	match answer.trim().to_lowercase().as_str() {
+		"y" | "yes" => Ok(()),
+		_ => Err(CliError::InvalidInput("Purge-all aborted".to_string())),
+	}
+}
+
+/// The paths of a purge/prefetch, either a comma separated list or read from a file/stdin with one path per line, which allows commas in paths
+#[derive(Debug, Args)]
+pub struct PathsArgs {
+	#[clap(short = 'p', long, conflicts_with = "paths-file")]
+	/// A comma separated list of paths, purged paths can contain wildcards (*)
+	paths: Option<String>,
+	#[clap(long)]
+	/// File with one path per line, `-` for stdin. Blank lines and lines starting with # are skipped.
+	paths_file: Option<PathBuf>,
+}
+
+impl PathsArgs {
+	/// Whether the paths are read from stdin, which can't be used for anything else then
+	pub fn reads_stdin(&self) -> bool {
+		self.paths.is_none() && self.paths_file.as_deref() == Some(Path::new("-"))
+	}
+
+	fn read(&self) -> Result<Vec<String>, CliError> {
+		let paths: Vec<String> = match (&self.paths, &self.paths_file) {
+			(Some(paths), _) => paths.split(',')
+				.filter(|s| !s.trim().is_empty())
+				.map(|s| s.to_string()).collect(),
+			(None, Some(file)) => read_paths_file(file, io::stdin())?,
+			(None, None) => vec![],
+		};
+
+		if paths.is_empty() {
+			return Err(CliError::InvalidInput("Please specify at least one path via --paths or --paths-file".to_string()));
 		}
+
+		Ok(paths)
 	}
 }
 
-#[derive(Deserialize)]
-struct PurgeAllResponse {
-	id: String,
-	#[serde(rename = "type")]
-	resource_type: String,
-	cdn: HashMap<String, ResourceId>,
-	state: String,
-	queued_at: String,
-	done_at: String,
+/// Reads the paths from the file or, if it's `-`, from stdin
+fn read_paths_file(file: &Path, mut stdin: impl Read) -> Result<Vec<String>, CliError> {
+	let content = if file == Path::new("-") {
+		let mut content = String::new();
+		stdin.read_to_string(&mut content)
+			.map_err(|err| CliError::InvalidInput(format!("Cannot read paths from stdin: {}", err)))?;
+		content
+	} else {
+		fs::read_to_string(file)
+			.map_err(|err| CliError::InvalidInput(format!("Cannot read paths file {}: {}", file.display(), err)))?
+	};
+
+	Ok(content.lines()
+		.map(|line| line.trim())
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(|line| line.to_string())
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn urls(urls: &[&str]) -> Vec<Url> {
+		urls.iter().map(|u| Url::parse(u).unwrap()).collect()
+	}
+
+	fn owners(owners: &[(&str, Option<ResourceId>)]) -> HashMap<String, Option<ResourceId>> {
+		owners.iter().map(|(host, owner)| (host.to_string(), *owner)).collect()
+	}
+
+	fn listed_job(id: &str, state: &str, queued_at: &str, done_at: Option<&str>) -> ListJobDetail {
+		ListJobDetail {
+			id: id.to_string(),
+			resource_type: "purge".to_string(),
+			cdn: HashMap::from([("id".to_string(), 12345)]),
+			paths_count: 1,
+			state: state.to_string(),
+			queued_at: queued_at.to_string(),
+			done_at: done_at.map(|d| d.to_string()),
+		}
+	}
+
+	fn time(time: &str) -> DateTime<Utc> {
+		DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+	}
+
+	#[test]
+	fn format_duration_leaves_out_leading_zero_units() {
+		assert_eq!(format_duration(0), "0s");
+		assert_eq!(format_duration(59), "59s");
+		assert_eq!(format_duration(60), "1m00s");
+		assert_eq!(format_duration(754), "12m34s");
+		assert_eq!(format_duration(3600), "1h00m00s");
+		assert_eq!(format_duration(90_123), "25h02m03s");
+	}
+
+	#[test]
+	fn is_finished_for_done_and_failed() {
+		assert!(is_finished("done"));
+		assert!(is_finished("failed"));
+		assert!(!is_finished("queued"));
+		assert!(!is_finished("running"));
+	}
+
+	#[test]
+	fn watched_jobs_leave_out_jobs_finished_before_the_watch() {
+		let listed = vec![
+			listed_job("old", "done", "2026-01-01T09:00:00+00:00", Some("2026-01-01T09:01:00+00:00")),
+			listed_job("queued", "queued", "2026-01-01T09:59:00+00:00", None),
+			listed_job("new", "done", "2026-01-01T10:00:30+00:00", Some("2026-01-01T10:00:40+00:00")),
+		];
+
+		let jobs = watched_jobs(12345, listed, &HashMap::new(), time("2026-01-01T10:00:00Z"), time("2026-01-01T10:01:00Z"));
+
+		let ids: Vec<&str> = jobs.iter().map(|j| j.id.as_str()).collect();
+		assert_eq!(ids, ["queued", "new"]);
+		// Until now while queued, until the job was done afterwards
+		assert_eq!(jobs[0].queued_for_secs, Some(120));
+		assert_eq!(jobs[1].queued_for_secs, Some(10));
+	}
+
+	#[test]
+	fn watched_jobs_keep_seen_jobs_and_their_previous_state() {
+		let states = HashMap::from([("a".to_string(), "queued".to_string()), ("b".to_string(), "queued".to_string())]);
+		let listed = vec![
+			listed_job("a", "done", "2026-01-01T09:00:00+00:00", Some("2026-01-01T10:00:30+00:00")),
+			listed_job("b", "queued", "2026-01-01T09:00:00+00:00", None),
+		];
+
+		let jobs = watched_jobs(12345, listed, &states, time("2026-01-01T10:00:00Z"), time("2026-01-01T10:01:00Z"));
+
+		assert_eq!(jobs.len(), 2);
+		assert_eq!(jobs[0].previous_state.as_deref(), Some("queued"));
+		assert_eq!(jobs[1].previous_state, None);
+	}
+
+	#[test]
+	fn until_done_stops_once_all_watched_jobs_are_finished() {
+		let states = HashMap::from([("a".to_string(), "queued".to_string())]);
+		let job = |state| listed_job("a", state, "2026-01-01T09:00:00+00:00", None);
+		let watched = |listed| watched_jobs(12345, listed, &states, time("2026-01-01T10:00:00Z"), time("2026-01-01T10:01:00Z"));
+
+		assert!(!is_all_finished(&watched(vec![job("queued"), job("failed")])));
+		assert!(is_all_finished(&watched(vec![job("done"), job("failed")])));
+		assert!(is_all_finished(&[]));
+	}
+
+	#[test]
+	fn watched_jobs_table_highlights_changed_jobs() {
+		let states = HashMap::from([("a".to_string(), "queued".to_string()), ("b".to_string(), "queued".to_string())]);
+		let listed = vec![
+			listed_job("a", "done", "2026-01-01T09:00:00+00:00", Some("2026-01-01T10:00:30+00:00")),
+			listed_job("b", "queued", "2026-01-01T09:00:00+00:00", None),
+		];
+		let jobs = watched_jobs(12345, listed, &states, time("2026-01-01T10:00:00Z"), time("2026-01-01T10:01:00Z"));
+
+		let lines = watched_jobs_table(&jobs, true);
+		assert_eq!(lines.len(), 4);
+		assert!(lines[2].starts_with("\x1b[1;33m") && lines[2].contains("queued -> done") && lines[2].ends_with("\x1b[0m"), "{:?}", lines[2]);
+		assert!(!lines[3].contains('\x1b') && lines[3].contains("queued"), "{:?}", lines[3]);
+
+		// Without a terminal, the change is still shown in the state column
+		let lines = watched_jobs_table(&jobs, false);
+		assert!(lines.iter().all(|line| !line.contains('\x1b')));
+		assert!(lines[2].contains("queued -> done"));
+	}
+
+	#[test]
+	fn read_paths_file_skips_comments_and_blank_lines() {
+		let file = std::env::temp_dir().join(format!("cdn77-paths-test-{}.txt", std::process::id()));
+		fs::write(&file, "# Changed in the release\n/index.html\n\n  /images/a,b.png  \r\n\t\n  # /skipped.css\n/app.js").unwrap();
+
+		let paths = read_paths_file(&file, io::empty()).unwrap();
+		fs::remove_file(&file).unwrap();
+
+		assert_eq!(paths, ["/index.html", "/images/a,b.png", "/app.js"]);
+	}
+
+	#[test]
+	fn read_paths_file_reads_stdin_for_dash() {
+		let paths = read_paths_file(Path::new("-"), "/a,b.html\n# comment\n\n/c.html\n".as_bytes()).unwrap();
+
+		assert_eq!(paths, ["/a,b.html", "/c.html"]);
+	}
+
+	#[test]
+	fn read_paths_file_fails_for_missing_file() {
+		let result = read_paths_file(Path::new("/nonexistent/paths.txt"), io::empty());
+
+		assert!(matches!(result, Err(CliError::InvalidInput(message)) if message.starts_with("Cannot read paths file /nonexistent/paths.txt")));
+	}
+
+	#[test]
+	fn split_urls_keeps_commas_within_urls() {
+		assert_eq!(split_urls("https://a.example.com/x?v=1,2,http://b.example.com/y, https://c.example.com/a,b"),
+				   ["https://a.example.com/x?v=1,2", "http://b.example.com/y", "https://c.example.com/a,b"]);
+		assert_eq!(split_urls("https://a.example.com/x,"), ["https://a.example.com/x,"]);
+		assert!(split_urls(" ").is_empty());
+	}
+
+	#[test]
+	fn group_paths_by_owner_groups_the_paths_per_resource() {
+		let urls = urls(&["https://cdn.example.com/a.png", "https://static.example.com/app.js", "https://www.example.com/b.png?v=1,2"]);
+		let owners = owners(&[("cdn.example.com", Some(1)), ("www.example.com", Some(1)), ("static.example.com", Some(2))]);
+
+		let grouped = group_paths_by_owner(&urls, &owners).unwrap();
+
+		assert_eq!(grouped, [(1, vec!["/a.png".to_string(), "/b.png?v=1,2".to_string()]), (2, vec!["/app.js".to_string()])]);
+	}
+
+	#[test]
+	fn group_paths_by_owner_removes_duplicate_paths_of_a_resource() {
+		let urls = urls(&["https://cdn.example.com/a.png", "http://www.example.com/a.png", "https://cdn.example.com/a.png#top"]);
+		let owners = owners(&[("cdn.example.com", Some(1)), ("www.example.com", Some(1))]);
+
+		assert_eq!(group_paths_by_owner(&urls, &owners).unwrap(), [(1, vec!["/a.png".to_string()])]);
+	}
+
+	#[test]
+	fn group_paths_by_owner_rejects_hosts_without_owner() {
+		let urls = urls(&["https://cdn.example.com/a.png", "https://other.example.org/b.png", "https://other.example.org/c.png"]);
+		let owners = owners(&[("cdn.example.com", Some(1)), ("other.example.org", None)]);
+
+		match group_paths_by_owner(&urls, &owners) {
+			Err(CliError::InvalidInput(message)) => assert_eq!(message, "No CDN resource owns the hosts other.example.org, nothing was purged"),
+			result => panic!("Unexpected result {:?}", result),
+		}
+	}
 }
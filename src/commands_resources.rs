@@ -0,0 +1,19 @@
+use cdn77::Cdn77Client;
+
+use crate::output::Output;
+use crate::util::CliError;
+
+pub async fn command_resources_list(client: &Cdn77Client, output: &Output) -> Result<(), CliError> {
+	let r = client.list_resources().await?;
+
+	output.render(&r, |r| {
+		println!("Found {} CDN resources", &r.len());
+
+		for resource in r {
+			println!("\nID={}\nLabel={}\nCDN URL={}\nCNAMEs={}",
+					 resource.id, resource.label.as_deref().unwrap_or("-"), resource.cdn_url, resource.cnames.join(", "));
+		}
+	});
+
+	Ok(())
+}
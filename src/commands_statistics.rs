@@ -1,429 +1,231 @@
-use std::fmt::{Display, Formatter};
-use std::process;
-use std::str::FromStr;
-
-use reqwest::{Client, StatusCode};
-use serde::Deserialize;
-use serde::Serialize;
-use serde_json::Value;
-
-use crate::{CDN77_API_BASE, EXIT_CODE_API_EXPECTED_ERROR, EXIT_CODE_API_UNEXPECTED_ERROR, EXIT_CODE_INVALID_INPUT, ResourceId};
-use crate::util::{handle_default_response_status_codes, parse_date_time_or_exit, parse_resource_ids_optional, read_body_or_return_default_error_text, send_http_request_return_response_or_exit};
-
-pub async fn command_stats_get_stats(client: Client, stat_type: &GetStatsType, from: &str, to: &str, resource_ids: &Option<String>,
-									 location_ids: &Option<String>, aggregation: &Option<String>) {
-	let from = parse_date_time_or_exit(from, "Start date/time is not in a correct format");
-	let to = parse_date_time_or_exit(to, "End date/time is not in a correct format");
-	let resource_ids = parse_resource_ids_optional(resource_ids);
-	let location_ids = parse_optional_location_ids(location_ids);
-
-	let request_url = format!("{}/stats/{}", CDN77_API_BASE, stat_type);
+use cdn77::{Cdn77Client, Cdn77Error, ResourceId};
+use cdn77::api_statistics::{Bandwidth95PercentileRequest, GetStatsRequest, GetStatsType, StatsByDataCenterRequest, StatsByResourceRequest, SUM_TYPES, SumByDataCenterRequest,
+							SumByResourceRequest, SumRequest};
+use clap::Args;
+
+use crate::config::Profile;
+use crate::output::Output;
+use crate::resource_resolver::ResourceResolver;
+use crate::util::{CliError, parse_date_time};
+
+pub async fn command_stats_get_stats(client: &Cdn77Client, output: &Output, stat_type: &GetStatsType, filter: &StatsFilter,
+									 aggregation: &Option<String>) -> Result<(), CliError> {
+
 	let request = GetStatsRequest {
-		from: from.timestamp(),
-		to: to.timestamp(),
-		cdn_ids: resource_ids,
-		location_ids,
+		from: filter.from,
+		to: filter.to,
+		cdn_ids: filter.cdn_ids.clone(),
+		location_ids: filter.location_ids.clone(),
 		aggregation: aggregation.clone(),
 	};
-	let response = send_http_request_return_response_or_exit(client.post(request_url).json(&request)).await;
-
-	match response.status() {
-		StatusCode::OK => {
-			// JSON parsing is just here to validate valid JSON was returned
-			match response.json::<Value>().await {
-				Ok(r) => {
-					println!("{}", serde_json::to_string_pretty(&r).unwrap());
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-				}
-			}
-		}
-		StatusCode::NOT_FOUND => {
-			eprintln!("Could not get stats for this type without grouping: {}", read_body_or_return_default_error_text(response).await);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
+
+	match client.get_stats(*stat_type, &request).await {
+		Ok(r) => {
+			output.render(&r, |r| println!("{}", serde_json::to_string_pretty(r).unwrap()));
+			Ok(())
 		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+		Err(Cdn77Error::NotFound { url, body }) => {
+			let message = format!("Could not get stats for this type without grouping: {}", body);
+			Err(CliError::api_with_message(Cdn77Error::NotFound { url, body }, message))
 		}
+		Err(err) => Err(err.into()),
 	}
 }
 
-fn parse_optional_location_ids(location_ids: &Option<String>) -> Option<Vec<String>> {
-	match location_ids {
-		Some(r) => Some(r.split(',').map(|r| r.trim()).filter(|r| !r.is_empty()).map(|s| s.to_string()).collect()),
-		None => None,
-	}
+/// Time range and filters shared by all stats commands
+#[derive(Debug, Args)]
+pub struct StatsFilterArgs {
+	#[clap(short = 'f', long)]
+	/// Start date/time in format: YYYY-MM-DD hh:mm
+	from: String,
+	#[clap(short = 'e', long)]
+	/// End date/time in format YYYY-MM-DD hh:mm
+	to: String,
+	#[clap(short = 'i', long)]
+	/// (opt) Comma separated IDs, labels or CNAMEs of CDN resources, defaults to the profile's resources and otherwise to all
+	resource_ids: Option<String>,
+	#[clap(short = 'l', long)]
+	/// (opt) Location names (e.g. 'prague'), defaults to all
+	location_ids: Option<String>,
+}
+
+/// The parsed [`StatsFilterArgs`]
+pub struct StatsFilter {
+	from: i64,
+	to: i64,
+	cdn_ids: Option<Vec<ResourceId>>,
+	location_ids: Option<Vec<String>>,
 }
 
-// TODO This is a tad of an overkill, we're converting string => enum => string. Maybe just validate string instead?
-#[derive(Debug)]
-pub enum GetStatsType {
-	Bandwidth,
-	Costs,
-	Headers,
-	HeadersDetail,
-	HitMiss,
-	HitMissDetail,
-	Traffic,
-	TrafficDetail,
+impl StatsFilterArgs {
+	/// Without `--resource-ids`, the default resources of the profile are used
+	pub async fn parse(&self, resolver: &ResourceResolver<'_>, profile: &Profile) -> Result<StatsFilter, CliError> {
+		Ok(StatsFilter {
+			from: parse_date_time(&self.from, "Start date/time is not in a correct format")?.timestamp(),
+			to: parse_date_time(&self.to, "End date/time is not in a correct format")?.timestamp(),
+			cdn_ids: resolver.resource_ids(&self.resource_ids, profile).await?,
+			location_ids: parse_optional_location_ids(&self.location_ids),
+		})
+	}
 }
 
-impl Display for GetStatsType {
-	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-		let value = match self {
-			GetStatsType::Bandwidth => "bandwidth",
-			GetStatsType::Costs => "costs",
-			GetStatsType::Headers => "headers",
-			GetStatsType::HeadersDetail => "headers-details",
-			GetStatsType::HitMiss => "hit-miss",
-			GetStatsType::HitMissDetail => "hit-miss-detail",
-			GetStatsType::Traffic => "traffic",
-			GetStatsType::TrafficDetail => "traffic-detail",
-		};
-		write!(f, "{}", value)
-	}
+fn parse_optional_location_ids(location_ids: &Option<String>) -> Option<Vec<String>> {
+	location_ids.as_ref()
+		.map(|r| r.split(',').map(|r| r.trim()).filter(|r| !r.is_empty()).map(|s| s.to_string()).collect())
 }
 
-impl FromStr for GetStatsType {
-	type Err = &'static str;
-
-	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		match s {
-			"bandwidth" => Ok(GetStatsType::Bandwidth),
-			"costs" => Ok(GetStatsType::Costs),
-			"headers" => Ok(GetStatsType::Headers),
-			"headers-detail" => Ok(GetStatsType::HeadersDetail),
-			"hit-miss" => Ok(GetStatsType::HitMiss),
-			"hit-miss-detail" => Ok(GetStatsType::HitMissDetail),
-			"traffic" => Ok(GetStatsType::Traffic),
-			"traffic-detail" => Ok(GetStatsType::TrafficDetail),
-			_ => Err("Invalid stat type"),
-		}
+fn validate_sum_stat_type(stat_type: &str) -> Result<(), CliError> {
+	if !SUM_TYPES.contains(&stat_type) {
+		return Err(CliError::InvalidInput(format!("Invalid stat type: {}", stat_type)));
 	}
-}
 
-#[derive(Serialize)]
-struct GetStatsRequest {
-	from: i64,
-	to: i64,
-	cdn_ids: Option<Vec<ResourceId>>,
-	location_ids: Option<Vec<String>>,
-	aggregation: Option<String>,
+	Ok(())
 }
 
 
-pub async fn command_stats_bandwidth_95th_percentile(client: Client, from: &str, to: &str, resource_ids: &Option<String>, location_ids: &Option<String>) {
-	let from = parse_date_time_or_exit(from, "Start date/time is not in a correct format");
-	let to = parse_date_time_or_exit(to, "End date/time is not in a correct format");
-	let resource_ids = parse_resource_ids_optional(resource_ids);
-	let location_ids = parse_optional_location_ids(location_ids);
+pub async fn command_stats_bandwidth_95th_percentile(client: &Cdn77Client, output: &Output, filter: &StatsFilter) -> Result<(), CliError> {
 
-	let request_url = format!("{}/stats/bandwidth/percentile", CDN77_API_BASE);
 	let request = Bandwidth95PercentileRequest {
-		from: from.timestamp(),
-		to: to.timestamp(),
-		cdn_ids: resource_ids,
-		location_ids,
+		from: filter.from,
+		to: filter.to,
+		cdn_ids: filter.cdn_ids.clone(),
+		location_ids: filter.location_ids.clone(),
 	};
-	let response = send_http_request_return_response_or_exit(client.post(request_url).json(&request)).await;
-
-	match response.status() {
-		StatusCode::OK => {
-			match response.json::<Bandwidth95PercentileResponse>().await {
-				Ok(r) => {
-					println!("Percentile: {}", r.percentile);
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-				}
-			}
-		}
-		StatusCode::NOT_FOUND => {
-			eprintln!("Could not get stats for this type without grouping: {}", read_body_or_return_default_error_text(response).await);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
+
+	match client.bandwidth_95th_percentile(&request).await {
+		Ok(r) => {
+			output.render(&r, |r| println!("Percentile: {}", r.percentile));
+			Ok(())
 		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+		Err(Cdn77Error::NotFound { url, body }) => {
+			let message = format!("Could not get stats for this type without grouping: {}", body);
+			Err(CliError::api_with_message(Cdn77Error::NotFound { url, body }, message))
 		}
+		Err(err) => Err(err.into()),
 	}
 }
 
-#[derive(Serialize)]
-struct Bandwidth95PercentileRequest {
-	from: i64,
-	to: i64,
-	cdn_ids: Option<Vec<ResourceId>>,
-	location_ids: Option<Vec<String>>,
-}
-
-#[derive(Deserialize)]
-struct Bandwidth95PercentileResponse {
-	percentile: i64,
-}
-
 
-pub async fn command_stats_by_resource(client: Client, stat_type: &GetStatsType, from: &str, to: &str, resource_ids: &Option<String>,
-									   location_ids: &Option<String>, aggregation: &Option<String>) {
-	let from = parse_date_time_or_exit(from, "Start date/time is not in a correct format");
-	let to = parse_date_time_or_exit(to, "End date/time is not in a correct format");
-	let resource_ids = parse_resource_ids_optional(resource_ids);
-	let location_ids = parse_optional_location_ids(location_ids);
+pub async fn command_stats_by_resource(client: &Cdn77Client, output: &Output, stat_type: &GetStatsType, filter: &StatsFilter,
+									   aggregation: &Option<String>) -> Result<(), CliError> {
 
-	let request_url = format!("{}/stats/cdns/{}", CDN77_API_BASE, stat_type);
 	let request = StatsByResourceRequest {
-		from: from.timestamp(),
-		to: to.timestamp(),
-		cdn_ids: resource_ids,
-		location_ids,
+		from: filter.from,
+		to: filter.to,
+		cdn_ids: filter.cdn_ids.clone(),
+		location_ids: filter.location_ids.clone(),
 		aggregation: aggregation.clone(),
 	};
-	let response = send_http_request_return_response_or_exit(client.post(request_url).json(&request)).await;
-
-	match response.status() {
-		StatusCode::OK => {
-			match response.json::<Value>().await {
-				Ok(r) => {
-					println!("{}", serde_json::to_string_pretty(&r).unwrap());
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-				}
-			}
-		}
-		StatusCode::NOT_FOUND => {
-			eprintln!("Couldn't get stat type grouped by resource: {}", read_body_or_return_default_error_text(response).await);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
+
+	match client.stats_by_resource(*stat_type, &request).await {
+		Ok(r) => {
+			output.render(&r, |r| println!("{}", serde_json::to_string_pretty(r).unwrap()));
+			Ok(())
 		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+		Err(Cdn77Error::NotFound { url, body }) => {
+			let message = format!("Couldn't get stat type grouped by resource: {}", body);
+			Err(CliError::api_with_message(Cdn77Error::NotFound { url, body }, message))
 		}
+		Err(err) => Err(err.into()),
 	}
 }
 
-#[derive(Serialize)]
-struct StatsByResourceRequest {
-	from: i64,
-	to: i64,
-	cdn_ids: Option<Vec<ResourceId>>,
-	location_ids: Option<Vec<String>>,
-	aggregation: Option<String>,
-}
-
 
-pub async fn command_stats_sum_by_resource(client: Client, stat_type: &String, from: &str, to: &str, resource_ids: &Option<String>,
-										   location_ids: &Option<String>) {
-	if !SUM_BY_RESOURCE_TYPE.contains(&&stat_type[..]) {
-		eprintln!("Invalid stat type: {}", stat_type);
-		process::exit(EXIT_CODE_INVALID_INPUT);
-	}
+pub async fn command_stats_sum_by_resource(client: &Cdn77Client, output: &Output, stat_type: &str, filter: &StatsFilter) -> Result<(), CliError> {
+	validate_sum_stat_type(stat_type)?;
 
-	let from = parse_date_time_or_exit(from, "Start date/time is not in a correct format");
-	let to = parse_date_time_or_exit(to, "End date/time is not in a correct format");
-	let resource_ids = parse_resource_ids_optional(resource_ids);
-	let location_ids = parse_optional_location_ids(location_ids);
 
-	let request_url = format!("{}/stats/cdns/sum/{}", CDN77_API_BASE, stat_type);
 	let request = SumByResourceRequest {
-		from: from.timestamp(),
-		to: to.timestamp(),
-		cdn_ids: resource_ids,
-		location_ids,
+		from: filter.from,
+		to: filter.to,
+		cdn_ids: filter.cdn_ids.clone(),
+		location_ids: filter.location_ids.clone(),
 	};
-	let response = send_http_request_return_response_or_exit(client.post(request_url).json(&request)).await;
-
-	match response.status() {
-		StatusCode::OK => {
-			match response.json::<Value>().await {
-				Ok(r) => {
-					println!("{}", serde_json::to_string_pretty(&r).unwrap());
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-				}
-			}
-		}
-		StatusCode::NOT_FOUND => {
-			eprintln!("Couldn't get stat sum by resource: {}", read_body_or_return_default_error_text(response).await);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
+
+	match client.sum_by_resource(stat_type, &request).await {
+		Ok(r) => {
+			output.render(&r, |r| println!("{}", serde_json::to_string_pretty(r).unwrap()));
+			Ok(())
 		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+		Err(Cdn77Error::NotFound { url, body }) => {
+			let message = format!("Couldn't get stat sum by resource: {}", body);
+			Err(CliError::api_with_message(Cdn77Error::NotFound { url, body }, message))
 		}
+		Err(err) => Err(err.into()),
 	}
 }
 
-const SUM_BY_RESOURCE_TYPE: [&'static str; 4] = ["headers", "traffic", "hit-miss", "costs"];
-
-#[derive(Serialize)]
-struct SumByResourceRequest {
-	from: i64,
-	to: i64,
-	cdn_ids: Option<Vec<ResourceId>>,
-	location_ids: Option<Vec<String>>,
-}
-
 
-pub async fn command_stats_by_data_center(client: Client, stat_type: &GetStatsType, from: &str, to: &str, resource_ids: &Option<String>,
-										  location_ids: &Option<String>, aggregation: &Option<String>) {
-	let from = parse_date_time_or_exit(from, "Start date/time is not in a correct format");
-	let to = parse_date_time_or_exit(to, "End date/time is not in a correct format");
-	let resource_ids = parse_resource_ids_optional(resource_ids);
-	let location_ids = parse_optional_location_ids(location_ids);
+pub async fn command_stats_by_data_center(client: &Cdn77Client, output: &Output, stat_type: &GetStatsType, filter: &StatsFilter,
+										  aggregation: &Option<String>) -> Result<(), CliError> {
 
-	let request_url = format!("{}/stats/datacenters/{}", CDN77_API_BASE, stat_type);
-	let request = StatsByResourceRequest {
-		from: from.timestamp(),
-		to: to.timestamp(),
-		cdn_ids: resource_ids,
-		location_ids,
+	let request = StatsByDataCenterRequest {
+		from: filter.from,
+		to: filter.to,
+		cdn_ids: filter.cdn_ids.clone(),
+		location_ids: filter.location_ids.clone(),
 		aggregation: aggregation.clone(),
 	};
-	let response = send_http_request_return_response_or_exit(client.post(request_url).json(&request)).await;
-
-	match response.status() {
-		StatusCode::OK => {
-			match response.json::<Value>().await {
-				Ok(r) => {
-					println!("{}", serde_json::to_string_pretty(&r).unwrap());
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-				}
-			}
-		}
-		StatusCode::NOT_FOUND => {
-			eprintln!("Couldn't get stat type grouped by datacenter: {}", read_body_or_return_default_error_text(response).await);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
+
+	match client.stats_by_data_center(*stat_type, &request).await {
+		Ok(r) => {
+			output.render(&r, |r| println!("{}", serde_json::to_string_pretty(r).unwrap()));
+			Ok(())
 		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+		Err(Cdn77Error::NotFound { url, body }) => {
+			let message = format!("Couldn't get stat type grouped by datacenter: {}", body);
+			Err(CliError::api_with_message(Cdn77Error::NotFound { url, body }, message))
 		}
+		Err(err) => Err(err.into()),
 	}
 }
 
-#[derive(Serialize)]
-struct StatsByDataCenterRequest {
-	from: i64,
-	to: i64,
-	cdn_ids: Option<Vec<ResourceId>>,
-	location_ids: Option<Vec<String>>,
-	aggregation: Option<String>,
-}
-
 
-pub async fn command_stats_sum_by_data_center(client: Client, stat_type: &String, from: &str, to: &str, resource_ids: &Option<String>,
-											  location_ids: &Option<String>) {
-	if !SUM_BY_DATA_CENTER_TYPE.contains(&&stat_type[..]) {
-		eprintln!("Invalid stat type: {}", stat_type);
-		process::exit(EXIT_CODE_INVALID_INPUT);
-	}
+pub async fn command_stats_sum_by_data_center(client: &Cdn77Client, output: &Output, stat_type: &str, filter: &StatsFilter) -> Result<(), CliError> {
+	validate_sum_stat_type(stat_type)?;
 
-	let from = parse_date_time_or_exit(from, "Start date/time is not in a correct format");
-	let to = parse_date_time_or_exit(to, "End date/time is not in a correct format");
-	let resource_ids = parse_resource_ids_optional(resource_ids);
-	let location_ids = parse_optional_location_ids(location_ids);
 
-	let request_url = format!("{}/stats/datacenters/sum/{}", CDN77_API_BASE, stat_type);
 	let request = SumByDataCenterRequest {
-		from: from.timestamp(),
-		to: to.timestamp(),
-		cdn_ids: resource_ids,
-		location_ids,
+		from: filter.from,
+		to: filter.to,
+		cdn_ids: filter.cdn_ids.clone(),
+		location_ids: filter.location_ids.clone(),
 	};
-	let response = send_http_request_return_response_or_exit(client.post(request_url).json(&request)).await;
-
-	match response.status() {
-		StatusCode::OK => {
-			match response.json::<Value>().await {
-				Ok(r) => {
-					println!("{}", serde_json::to_string_pretty(&r).unwrap());
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-				}
-			}
-		}
-		StatusCode::NOT_FOUND => {
-			eprintln!("Couldn't get stat sum by data center: {}", read_body_or_return_default_error_text(response).await);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
+
+	match client.sum_by_data_center(stat_type, &request).await {
+		Ok(r) => {
+			output.render(&r, |r| println!("{}", serde_json::to_string_pretty(r).unwrap()));
+			Ok(())
 		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+		Err(Cdn77Error::NotFound { url, body }) => {
+			let message = format!("Couldn't get stat sum by data center: {}", body);
+			Err(CliError::api_with_message(Cdn77Error::NotFound { url, body }, message))
 		}
+		Err(err) => Err(err.into()),
 	}
 }
 
-const SUM_BY_DATA_CENTER_TYPE: [&'static str; 4] = ["headers", "traffic", "hit-miss", "costs"];
 
-#[derive(Serialize)]
-struct SumByDataCenterRequest {
-	from: i64,
-	to: i64,
-	cdn_ids: Option<Vec<ResourceId>>,
-	location_ids: Option<Vec<String>>,
-}
+pub async fn command_stats_sum(client: &Cdn77Client, output: &Output, stat_type: &str, filter: &StatsFilter) -> Result<(), CliError> {
+	validate_sum_stat_type(stat_type)?;
 
 
-pub async fn command_stats_sum(client: Client, stat_type: &String, from: &str, to: &str, resource_ids: &Option<String>, location_ids: &Option<String>) {
-	if !SUM_TYPE.contains(&&stat_type[..]) {
-		eprintln!("Invalid stat type: {}", stat_type);
-		process::exit(EXIT_CODE_INVALID_INPUT);
-	}
-
-	let from = parse_date_time_or_exit(from, "Start date/time is not in a correct format");
-	let to = parse_date_time_or_exit(to, "End date/time is not in a correct format");
-	let resource_ids = parse_resource_ids_optional(resource_ids);
-	let location_ids = parse_optional_location_ids(location_ids);
-
-	let request_url = format!("{}/stats/sum/{}", CDN77_API_BASE, stat_type);
 	let request = SumRequest {
-		from: from.timestamp(),
-		to: to.timestamp(),
-		cdn_ids: resource_ids,
-		location_ids,
+		from: filter.from,
+		to: filter.to,
+		cdn_ids: filter.cdn_ids.clone(),
+		location_ids: filter.location_ids.clone(),
 	};
-	let response = send_http_request_return_response_or_exit(client.post(request_url).json(&request)).await;
-
-	match response.status() {
-		StatusCode::OK => {
-			match response.json::<SumResponse>().await {
-				Ok(r) => {
-					println!("Sum: {}", r.sum);
-				}
-				Err(err) => {
-					eprintln!("Failed to deserialize response, e={:?}", err);
-					process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-				}
-			}
-		}
-		StatusCode::NOT_FOUND => {
-			eprintln!("Couldn't get stats sum: {}", read_body_or_return_default_error_text(response).await);
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
+
+	match client.sum(stat_type, &request).await {
+		Ok(r) => {
+			output.render(&r, |r| println!("Sum: {}", r.sum));
+			Ok(())
 		}
-		_ => {
-			handle_default_response_status_codes(response).await;
+		Err(Cdn77Error::NotFound { url, body }) => {
+			let message = format!("Couldn't get stats sum: {}", body);
+			Err(CliError::api_with_message(Cdn77Error::NotFound { url, body }, message))
 		}
+		Err(err) => Err(err.into()),
 	}
 }
-
-const SUM_TYPE: [&'static str; 4] = ["headers", "traffic", "hit-miss", "costs"];
-
-#[derive(Serialize)]
-struct SumRequest {
-	from: i64,
-	to: i64,
-	cdn_ids: Option<Vec<ResourceId>>,
-	location_ids: Option<Vec<String>>,
-}
-
-#[derive(Deserialize)]
-struct SumResponse {
-	sum: f64,
-}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cdn77::ResourceId;
+use serde::{Deserialize, Deserializer};
+
+use crate::output::OutputFormat;
+use crate::token::run_token_command;
+use crate::util::CliError;
+
+/// The config file, it holds named profiles, e.g. one per CDN77 account
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+	/// Used if no profile is selected via `--profile`/`CDN77_PROFILE`
+	default_profile: Option<String>,
+	#[serde(default)]
+	profiles: HashMap<String, Profile>,
+}
+
+/// Settings of a profile, all of them can be overridden by flags and env variables
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+	/// The API token, prefer `api_token_env` to keep it out of the file
+	pub api_token: Option<String>,
+	/// Name of an env variable holding the API token
+	pub api_token_env: Option<String>,
+	/// Shell command printing the API token, e.g. the CLI of a secrets manager
+	pub token_command: Option<String>,
+	pub api_base: Option<String>,
+	/// Used by commands if `--resource-id(s)` is missing. Like the args, these can be IDs, labels or CNAMEs.
+	#[serde(default, deserialize_with = "resource_references")]
+	pub resource_ids: Vec<String>,
+	/// Resources on which purge-all is refused unless `--force-protected` is given, IDs, labels or CNAMEs
+	#[serde(default, deserialize_with = "resource_references")]
+	pub protected_resource_ids: Vec<String>,
+	pub output: Option<String>,
+	/// Seconds the resource list used to resolve labels and CNAMEs is cached
+	pub resource_cache_ttl: Option<u64>,
+	pub timeout: Option<u64>,
+	pub connect_timeout: Option<u64>,
+	/// Proxy URL for all requests
+	pub proxy: Option<String>,
+	/// PEM file with additional CA certificates to trust
+	pub ca_cert: Option<PathBuf>,
+}
+
+impl Config {
+	/// Loads the config from the given file or the default location. A missing default file is fine, a missing given file isn't.
+	pub fn load(file: Option<&Path>) -> Result<Config, CliError> {
+		let file = match (file, default_file()) {
+			(Some(file), _) => file.to_path_buf(),
+			(None, Some(file)) if file.exists() => file,
+			(None, _) => return Ok(Config::default()),
+		};
+
+		let content = fs::read_to_string(&file)
+			.map_err(|err| CliError::InvalidInput(format!("Cannot read config file {}: {}", file.display(), err)))?;
+		toml::from_str(&content)
+			.map_err(|err| CliError::InvalidInput(format!("Invalid config file {}: {}", file.display(), err)))
+	}
+
+	/// Returns the selected profile, or the default profile if none was selected. Without either, all settings are empty.
+	pub fn profile(&self, name: Option<&str>) -> Result<Profile, CliError> {
+		match name.or(self.default_profile.as_deref()) {
+			Some(name) => self.profiles.get(name)
+				.cloned()
+				.ok_or_else(|| CliError::InvalidInput(format!("Profile '{}' doesn't exist in the config file", name))),
+			None => Ok(Profile::default()),
+		}
+	}
+}
+
+impl Profile {
+	/// The API token of the profile, either set directly, read from the configured env variable or printed by the token command
+	pub fn api_token(&self) -> Result<Option<String>, CliError> {
+		match (&self.api_token, &self.api_token_env, &self.token_command) {
+			(Some(token), None, None) => Ok(Some(token.to_string())),
+			(None, Some(var), None) => env::var(var)
+				.map(Some)
+				.map_err(|_| CliError::InvalidInput(format!("The env variable {} of the profile's api_token_env isn't set", var))),
+			(None, None, Some(command)) => run_token_command(command).map(Some),
+			(None, None, None) => Ok(None),
+			_ => Err(CliError::InvalidInput("The profile must only set one of api_token, api_token_env and token_command".to_string())),
+		}
+	}
+
+	pub fn output(&self) -> Result<Option<OutputFormat>, CliError> {
+		self.output.as_deref()
+			.map(|output| output.parse().map_err(|err: &str| CliError::InvalidInput(format!("Profile output: {}", err))))
+			.transpose()
+	}
+
+	/// The single default resource of the profile, for commands which work on one resource
+	pub fn default_resource(&self) -> Result<&str, CliError> {
+		match self.resource_ids.as_slice() {
+			[reference] => Ok(reference),
+			[] => Err(CliError::InvalidInput("No resource ID given, use --resource-id or set resource_ids in the profile".to_string())),
+			_ => Err(CliError::InvalidInput("The profile has multiple default resource IDs, please pick one with --resource-id".to_string())),
+		}
+	}
+}
+
+/// Resource references may be written as numbers or strings, e.g. `[12345, "www"]`
+fn resource_references<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+	#[derive(Deserialize)]
+	#[serde(untagged, expecting = "expected a resource ID, label or CNAME")]
+	enum Reference {
+		Id(ResourceId),
+		Name(String),
+	}
+
+	Ok(Vec::<Reference>::deserialize(deserializer)?
+		.into_iter()
+		.map(|reference| match reference {
+			Reference::Id(resource_id) => resource_id.to_string(),
+			Reference::Name(name) => name,
+		})
+		.collect())
+}
+
+/// `$XDG_CONFIG_HOME/cdn77/config.toml`, defaulting to `~/.config/cdn77/config.toml`
+fn default_file() -> Option<PathBuf> {
+	env::var_os("XDG_CONFIG_HOME")
+		.filter(|dir| !dir.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+		.map(|dir| dir.join("cdn77").join("config.toml"))
+}
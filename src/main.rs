@@ -1,25 +1,48 @@
-extern crate core;
+use std::{env, io, process};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use std::env;
-
-use clap::{Parser, Subcommand};
+use cdn77::{CDN77_API_BASE, Cdn77Client, Cdn77Error};
+use cdn77::api_jobs::JobType;
+use cdn77::api_statistics::GetStatsType;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
 use dotenv::dotenv;
-use reqwest::{Client, header};
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
 
 use crate::commands_billing::command_billing_get_credit_balance;
-use crate::commands_jobs::{command_jobs_detail, command_jobs_list, command_jobs_prefetch, command_jobs_purge, command_jobs_purge_all, JobType};
-use crate::commands_statistics::{command_stats_bandwidth_95th_percentile, command_stats_by_data_center, command_stats_by_resource, command_stats_get_stats, command_stats_sum, command_stats_sum_by_data_center, command_stats_sum_by_resource, GetStatsType};
+use crate::commands_docs::{command_completions, command_man};
+use crate::commands_jobs::{BatchArgs, DirArgs, GitDiffArgs, PathsArgs, SitemapArgs, WaitArgs, command_jobs_detail, command_jobs_list, command_jobs_prefetch,
+						   command_jobs_prefetch_dir, command_jobs_prefetch_sitemap, command_jobs_purge, command_jobs_purge_all, command_jobs_purge_dir, command_jobs_purge_git,
+						   command_jobs_purge_urls, command_jobs_watch};
+use crate::commands_statistics::{StatsFilterArgs, command_stats_bandwidth_95th_percentile, command_stats_by_data_center, command_stats_by_resource, command_stats_get_stats, command_stats_sum, command_stats_sum_by_data_center, command_stats_sum_by_resource};
+use crate::commands_resources::command_resources_list;
 use crate::commands_storage::{command_storage_detail, command_storage_list};
-use crate::util::ResourceId;
+use crate::config::{Config, Profile};
+use crate::output::{ErrorFormat, LogFormat, Output, OutputFormat};
+use crate::resource_resolver::ResourceResolver;
+use crate::token::{read_token_file, read_token_stdin};
+use crate::util::{CliError, print_dry_run_request};
 
 mod commands_billing;
+mod commands_docs;
 mod commands_jobs;
+mod commands_resources;
 mod commands_storage;
 mod commands_statistics;
+mod config;
+mod output;
+mod path_sources;
+mod resource_resolver;
+mod token;
 mod util;
 
-pub const CDN77_API_BASE: &str = "https://api.cdn77.com/v3";
-const USER_AGENT: &'static str = "cdn77-api-cli-client (https://github.com/misternerd/cdn77-api-cli-client)";
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_RESOURCE_CACHE_TTL_SECS: u64 = 300;
 
 /// The user provided some unexpected/invalid input
 pub const EXIT_CODE_INVALID_INPUT: i32 = 2;
@@ -27,6 +50,8 @@ pub const EXIT_CODE_INVALID_INPUT: i32 = 2;
 pub const EXIT_CODE_API_EXPECTED_ERROR: i32 = 3;
 /// The API provided a non-success code, but it is unexpected (like "invalid input" or "invalid HTTP method")
 pub const EXIT_CODE_API_UNEXPECTED_ERROR: i32 = 4;
+/// The jobs weren't done within the time given by `--wait-timeout`
+pub const EXIT_CODE_WAIT_TIMEOUT: i32 = 5;
 
 
 #[derive(Parser)]
@@ -41,6 +66,66 @@ struct CliOpts {
 	#[clap(short = 'a', long)]
 	/// Either provide the token (dangerous!) or create an environment variable `CDN77_API_TOKEN` (preferred)
 	api_token: Option<String>,
+	#[clap(long)]
+	/// (opt) Read the API token from this file, it must only be readable by the owner
+	api_token_file: Option<PathBuf>,
+	#[clap(long)]
+	/// (opt) Read the API token from stdin
+	api_token_stdin: bool,
+	#[clap(short = 'o', long, global = true, env = "CDN77_OUTPUT")]
+	/// Output format: json, yaml, table, csv or text (default). Only results go to stdout for the machine-readable formats.
+	output: Option<OutputFormat>,
+	#[clap(long, global = true, env = "CDN77_PROFILE")]
+	/// (opt) Profile of the config file to use, otherwise its default_profile is used if set
+	profile: Option<String>,
+	#[clap(long, global = true, env = "CDN77_CONFIG")]
+	/// (opt) Config file with profiles, defaults to ~/.config/cdn77/config.toml
+	config: Option<PathBuf>,
+	#[clap(short = 'v', long, global = true, parse(from_occurrences))]
+	/// Log every API call to stderr: -v for method, URL, status and latency, -vv adds headers and bodies. The token is redacted.
+	verbose: u8,
+	#[clap(long, global = true, env = "CDN77_LOG_FORMAT", default_value = "text")]
+	/// Format of the -v logs: text or json (one object per line)
+	log_format: LogFormat,
+	#[clap(long, global = true, env = "CDN77_ERROR_FORMAT", default_value = "text")]
+	/// Format of error messages on stderr: text or json with code, http_status, message, api_errors and request_url
+	error_format: ErrorFormat,
+	#[clap(long, global = true, env = "CDN77_API_BASE")]
+	/// Base URL of the API, e.g. to route requests through a gateway or to use a local mock server. Defaults to https://api.cdn77.com/v3
+	api_base: Option<String>,
+	#[clap(long, global = true, env = "CDN77_RETRIES", default_value = "0")]
+	/// How often to retry requests on transport errors, 429 and 5xx. Purge/prefetch jobs are only retried if that's safe.
+	retries: u32,
+	#[clap(long, global = true, env = "CDN77_RETRY_MAX_WAIT", default_value = "30")]
+	/// Maximum seconds to wait between retries, a longer Retry-After from the API stops retrying
+	retry_max_wait: u64,
+	#[clap(long, global = true, env = "CDN77_RATE_LIMIT")]
+	/// (opt) Maximum requests per second sent to the API, e.g. 0.5 for one request every two seconds
+	rate_limit: Option<f64>,
+	#[clap(long, global = true, env = "CDN77_TIMEOUT")]
+	/// Timeout in seconds of a single request, defaults to 60
+	timeout: Option<u64>,
+	#[clap(long, global = true, env = "CDN77_CONNECT_TIMEOUT")]
+	/// Timeout in seconds for establishing a connection, defaults to 10
+	connect_timeout: Option<u64>,
+	#[clap(long, global = true, env = "CDN77_PROXY")]
+	/// (opt) Proxy URL for all requests, otherwise the env variables `HTTPS_PROXY`/`HTTP_PROXY` are used if set
+	proxy: Option<String>,
+	#[clap(long, global = true, env = "CDN77_CA_CERT")]
+	/// (opt) PEM file with additional CA certificates to trust, e.g. of a TLS intercepting proxy
+	ca_cert: Option<PathBuf>,
+	#[clap(long, global = true, env = "CDN77_RESOURCE_CACHE_TTL")]
+	/// Seconds the resource list used to resolve labels and CNAMEs is cached, defaults to 300. 0 disables the cache.
+	resource_cache_ttl: Option<u64>,
+	#[clap(long, global = true)]
+	/// Print the method, URL and body of requests which would change something (e.g. purge) instead of sending them
+	dry_run: bool,
+	#[clap(long, global = true, conflicts_with = "replay")]
+	/// (opt) Write every request/response pair to fixture files in this dir, the API token is redacted
+	record: Option<PathBuf>,
+	#[clap(long, global = true)]
+	/// (opt) Serve responses from fixture files in this dir instead of calling the API, no API token is needed
+	replay: Option<PathBuf>,
 	#[clap(subcommand)]
 	command: RootCommands,
 }
@@ -50,6 +135,11 @@ enum RootCommands {
 	#[clap(subcommand)]
 	/// Information about credit balance
 	Billing(BillingCommands),
+	/// Print a shell completion script, e.g. `cdn77-client completions bash > /etc/bash_completion.d/cdn77-client`
+	Completions {
+		/// The shell: bash, zsh, fish, powershell or elvish
+		shell: Shell,
+	},
 	#[clap(subcommand)]
 	/// Status and commands for/of puring and prefetching
 	Jobs(JobsCommands),
@@ -65,11 +155,27 @@ enum RootCommands {
 	#[clap(subcommand)]
 	/// Get statistics
 	Statistics(StatisticsCommands),
+	/// Print the man page in roff format
+	Man {
+		#[clap(long)]
+		/// (opt) Write one page per command into this dir instead, e.g. cdn77-client-jobs-purge.1
+		dir: Option<PathBuf>,
+	},
 	#[clap(subcommand)]
 	/// Infos about storage locations
 	Storage(StorageCommands),
 }
 
+impl RootCommands {
+	/// Whether the command reads its input from stdin
+	fn reads_stdin(&self) -> bool {
+		match self {
+			RootCommands::Jobs(JobsCommands::Prefetch { paths, .. }) | RootCommands::Jobs(JobsCommands::Purge { paths, .. }) => paths.reads_stdin(),
+			_ => false,
+		}
+	}
+}
+
 #[derive(Debug, Subcommand)]
 enum BillingCommands {
 	/// List the current credit balance
@@ -81,48 +187,134 @@ enum JobsCommands {
 	/// List all jobs of a certain type
 	List {
 		#[clap(short = 'i', long)]
-		/// The ID of the resource which you'd like to purge files from
-		resource_id: ResourceId,
+		/// The ID, label or CNAME of the resource which you'd like to purge files from, defaults to the profile's resource
+		resource_id: Option<String>,
 		#[clap(short = 't', long)]
 		/// Which jobs to list (prefetch, purge, purge-all)
 		job_type: JobType,
 	},
+	/// Show a live table of the queued jobs, which is refreshed until interrupted
+	Watch {
+		#[clap(short = 'i', long)]
+		/// (opt) Comma separated IDs, labels or CNAMEs of the resources to watch, defaults to the profile's resources
+		resource_ids: Option<String>,
+		#[clap(short = 't', long, default_value = "prefetch,purge,purge-all")]
+		/// Comma separated job types to watch
+		job_types: String,
+		#[clap(long, default_value = "5")]
+		/// Seconds between two refreshes
+		interval: u64,
+		#[clap(long)]
+		/// Exit once all watched jobs are done
+		until_done: bool,
+	},
 	/// Display details about a job
 	Detail {
 		#[clap(short = 'i', long)]
-		/// The ID of the resource which you'd like to purge files from
-		resource_id: ResourceId,
-		#[clap(short = 'i', long)]
-		/// The ID of the resource which you'd like to purge files from
+		/// The ID, label or CNAME of the resource which you'd like to purge files from, defaults to the profile's resource
+		resource_id: Option<String>,
+		#[clap(short = 'j', long)]
+		/// The ID of the job to show
 		job_id: String,
 	},
 	/// Prefetch a list of files on a CDN resource
 	Prefetch {
 		#[clap(short = 'i', long)]
-		/// The ID of the resource which you'd like to purge files from
-		resource_id: ResourceId,
-		#[clap(short = 'p', long)]
-		/// A comma separated list of paths to prefetch
-		paths: String,
+		/// The ID, label or CNAME of the resource which you'd like to purge files from, defaults to the profile's resource
+		resource_id: Option<String>,
+		#[clap(flatten)]
+		paths: PathsArgs,
 		#[clap(short = 'u', long)]
 		/// Use when host header forwarding is active on your CDN Resource
 		upstream_host: Option<String>,
+		#[clap(flatten)]
+		batch: BatchArgs,
+		#[clap(flatten)]
+		wait: WaitArgs,
+	},
+	/// Prefetch the URLs of a local sitemap.xml or sitemap index
+	PrefetchSitemap {
+		#[clap(short = 'i', long)]
+		/// The ID, label or CNAME of the resource which you'd like to prefetch files on, defaults to the profile's resource
+		resource_id: Option<String>,
+		#[clap(flatten)]
+		sitemap: SitemapArgs,
+		#[clap(short = 'u', long)]
+		/// Use when host header forwarding is active on your CDN Resource
+		upstream_host: Option<String>,
+		#[clap(flatten)]
+		batch: BatchArgs,
+		#[clap(flatten)]
+		wait: WaitArgs,
+	},
+	/// Prefetch the files of a local dir, e.g. the build output
+	PrefetchDir {
+		#[clap(short = 'i', long)]
+		/// The ID, label or CNAME of the resource which you'd like to prefetch files on, defaults to the profile's resource
+		resource_id: Option<String>,
+		#[clap(flatten)]
+		dir: DirArgs,
+		#[clap(short = 'u', long)]
+		/// Use when host header forwarding is active on your CDN Resource
+		upstream_host: Option<String>,
+		#[clap(flatten)]
+		batch: BatchArgs,
+		#[clap(flatten)]
+		wait: WaitArgs,
 	},
 	/// Purge a list of files/paths from a resource
 	Purge {
 		#[clap(short = 'i', long)]
-		/// The ID of the resource which you'd like to purge files from
-		resource_id: ResourceId,
-		#[clap(short = 'p', long)]
-		/// A comma seperated list of paths you'd like to clear.
-		/// Can contain wildcards (*)
-		paths: String,
+		/// The ID, label or CNAME of the resource which you'd like to purge files from, defaults to the profile's resource
+		resource_id: Option<String>,
+		#[clap(flatten)]
+		paths: PathsArgs,
+		#[clap(long, alias = "url", conflicts_with_all = &["paths", "paths-file", "resource-id"])]
+		/// Full URLs, possibly on multiple hosts, comma separated or repeated. Each one is purged on the resource with its host as CDN URL or
+		/// CNAME. Commas within a URL are kept, only a comma followed by http:// or https:// starts the next URL.
+		urls: Vec<String>,
+		#[clap(flatten)]
+		batch: BatchArgs,
+		#[clap(flatten)]
+		wait: WaitArgs,
+	},
+	/// Purge the files of a local dir, e.g. the build output
+	PurgeDir {
+		#[clap(short = 'i', long)]
+		/// The ID, label or CNAME of the resource which you'd like to purge files from, defaults to the profile's resource
+		resource_id: Option<String>,
+		#[clap(flatten)]
+		dir: DirArgs,
+		#[clap(flatten)]
+		batch: BatchArgs,
+		#[clap(flatten)]
+		wait: WaitArgs,
+	},
+	/// Purge the files which changed between two git revisions, including deleted ones
+	PurgeGit {
+		#[clap(short = 'i', long)]
+		/// The ID, label or CNAME of the resource which you'd like to purge files from, defaults to the profile's resource
+		resource_id: Option<String>,
+		#[clap(flatten)]
+		git: GitDiffArgs,
+		#[clap(flatten)]
+		batch: BatchArgs,
+		#[clap(flatten)]
+		wait: WaitArgs,
 	},
 	/// Purge all files from a specific CDN resource
 	PurgeAll {
 		#[clap(short = 'i', long)]
-		/// The ID of the resource which you'd like to purge all files from
-		resource_id: ResourceId,
+		/// The ID, label or CNAME of the resource which you'd like to purge all files from, defaults to the profile's resource
+		resource_id: Option<String>,
+		#[clap(short = 'y', long)]
+		/// Don't ask for confirmation, required when running non-interactively
+		yes: bool,
+		#[clap(long)]
+		/// Purge all files even if the resource is protected in the config profile
+		force_protected: bool,
+		#[clap(flatten)]
+		wait: WaitArgs,
 	},
 }
 
@@ -145,54 +337,24 @@ enum StatisticsCommands {
 		#[clap(short = 't', long)]
 		/// Stat type: bandwidth,costs,headers,headers-detail,hit-miss,hit-miss-detail,traffic,traffic-detail,traffic-miss
 		stat_type: GetStatsType,
-		#[clap(short = 'f', long)]
-		/// Start date/time in format: YYYY-MM-DD hh:mm
-		from: String,
-		#[clap(short = 'e', long)]
-		/// End date/time in format YYYY-MM-DD hh:mm
-		to: String,
-		#[clap(short = 'i', long)]
-		/// (opt) IDs of CDN resources, defaults to all
-		resource_ids: Option<String>,
-		#[clap(short = 'l', long)]
-		/// (opt) Location names (e.g. 'prague'), defaults to all
-		location_ids: Option<String>,
+		#[clap(flatten)]
+		filter: StatsFilterArgs,
 		#[clap(short = 'a', long)]
 		/// Aggregation, examples from docs: 5-m, 1-h, 1-d, 1-month (minutes must be divisible by five)
 		aggregation: Option<String>,
 	},
 	/// Get bandwidth's 95th percentile
 	Bandwidth95Percentile {
-		#[clap(short = 'f', long)]
-		/// Start date/time in format: YYYY-MM-DD hh:mm
-		from: String,
-		#[clap(short = 'e', long)]
-		/// End date/time in format YYYY-MM-DD hh:mm
-		to: String,
-		#[clap(short = 'i', long)]
-		/// (opt) IDs of CDN resources, defaults to all
-		resource_ids: Option<String>,
-		#[clap(short = 'l', long)]
-		/// (opt) Location names (e.g. 'prague'), defaults to all
-		location_ids: Option<String>,
+		#[clap(flatten)]
+		filter: StatsFilterArgs,
 	},
 	/// Get stats by CDN resource
 	StatsByResource {
 		#[clap(short = 't', long)]
 		/// Stat type: bandwidth,costs,headers,headers-detail,hit-miss,hit-miss-detail,traffic,traffic-detail,traffic-miss
 		stat_type: GetStatsType,
-		#[clap(short = 'f', long)]
-		/// Start date/time in format: YYYY-MM-DD hh:mm
-		from: String,
-		#[clap(short = 'e', long)]
-		/// End date/time in format YYYY-MM-DD hh:mm
-		to: String,
-		#[clap(short = 'i', long)]
-		/// (opt) IDs of CDN resources, defaults to all
-		resource_ids: Option<String>,
-		#[clap(short = 'l', long)]
-		/// (opt) Location names (e.g. 'prague'), defaults to all
-		location_ids: Option<String>,
+		#[clap(flatten)]
+		filter: StatsFilterArgs,
 		#[clap(short = 'a', long)]
 		/// Aggregation, examples from docs: 5-m, 1-h, 1-d, 1-month (minutes must be divisible by five)
 		aggregation: Option<String>,
@@ -202,36 +364,16 @@ enum StatisticsCommands {
 		#[clap(short = 't', long)]
 		/// Stat type: headers, traffic, hit-miss, costs
 		stat_type: String,
-		#[clap(short = 'f', long)]
-		/// Start date/time in format: YYYY-MM-DD hh:mm
-		from: String,
-		#[clap(short = 'e', long)]
-		/// End date/time in format YYYY-MM-DD hh:mm
-		to: String,
-		#[clap(short = 'i', long)]
-		/// (opt) IDs of CDN resources, defaults to all
-		resource_ids: Option<String>,
-		#[clap(short = 'l', long)]
-		/// (opt) Location names (e.g. 'prague'), defaults to all
-		location_ids: Option<String>,
+		#[clap(flatten)]
+		filter: StatsFilterArgs,
 	},
 	/// Get stats per data center
 	StatsByDataCenter {
 		#[clap(short = 't', long)]
 		/// Stat type: bandwidth,costs,headers,headers-detail,hit-miss,hit-miss-detail,traffic,traffic-detail,traffic-miss
 		stat_type: GetStatsType,
-		#[clap(short = 'f', long)]
-		/// Start date/time in format: YYYY-MM-DD hh:mm
-		from: String,
-		#[clap(short = 'e', long)]
-		/// End date/time in format YYYY-MM-DD hh:mm
-		to: String,
-		#[clap(short = 'i', long)]
-		/// (opt) IDs of CDN resources, defaults to all
-		resource_ids: Option<String>,
-		#[clap(short = 'l', long)]
-		/// (opt) Location names (e.g. 'prague'), defaults to all
-		location_ids: Option<String>,
+		#[clap(flatten)]
+		filter: StatsFilterArgs,
 		#[clap(short = 'a', long)]
 		/// Aggregation, examples from docs: 5-m, 1-h, 1-d, 1-month (minutes must be divisible by five)
 		aggregation: Option<String>,
@@ -241,36 +383,16 @@ enum StatisticsCommands {
 		#[clap(short = 't', long)]
 		/// Stat type: headers, traffic, hit-miss, costs
 		stat_type: String,
-		#[clap(short = 'f', long)]
-		/// Start date/time in format: YYYY-MM-DD hh:mm
-		from: String,
-		#[clap(short = 'e', long)]
-		/// End date/time in format YYYY-MM-DD hh:mm
-		to: String,
-		#[clap(short = 'i', long)]
-		/// (opt) IDs of CDN resources, defaults to all
-		resource_ids: Option<String>,
-		#[clap(short = 'l', long)]
-		/// (opt) Location names (e.g. 'prague'), defaults to all
-		location_ids: Option<String>,
+		#[clap(flatten)]
+		filter: StatsFilterArgs,
 	},
 	/// Get sum
 	Sum {
 		#[clap(short = 't', long)]
 		/// Stat type: headers, traffic, hit-miss, costs
 		stat_type: String,
-		#[clap(short = 'f', long)]
-		/// Start date/time in format: YYYY-MM-DD hh:mm
-		from: String,
-		#[clap(short = 'e', long)]
-		/// End date/time in format YYYY-MM-DD hh:mm
-		to: String,
-		#[clap(short = 'i', long)]
-		/// (opt) IDs of CDN resources, defaults to all
-		resource_ids: Option<String>,
-		#[clap(short = 'l', long)]
-		/// (opt) Location names (e.g. 'prague'), defaults to all
-		location_ids: Option<String>,
+		#[clap(flatten)]
+		filter: StatsFilterArgs,
 	},
 }
 
@@ -289,33 +411,139 @@ enum StorageCommands {
 #[tokio::main]
 async fn main() {
 	dotenv().ok();
-	let cli_opts = CliOpts::parse();
-	let client = create_cdn77_client(&cli_opts);
+	let cli_opts = match CliOpts::try_parse() {
+		Ok(cli_opts) => cli_opts,
+		Err(err) if err.use_stderr() && error_format_from_args() == ErrorFormat::Json => {
+			CliError::InvalidInput(clap_error_message(&err)).report(ErrorFormat::Json);
+			process::exit(EXIT_CODE_INVALID_INPUT);
+		}
+		Err(err) => err.exit(),
+	};
 
+	init_logging(cli_opts.verbose, cli_opts.log_format);
+
+	if let Err(err) = run_command(&cli_opts).await {
+		err.report(cli_opts.error_format);
+		process::exit(err.exit_code());
+	}
+}
+
+/// Only the events of the API client are logged, those of the HTTP stack below it are too noisy. Warnings are always logged.
+fn init_logging(verbose: u8, format: LogFormat) {
+	let level = match verbose {
+		0 => Level::WARN,
+		1 => Level::DEBUG,
+		_ => Level::TRACE,
+	};
+
+	let filter = Targets::new().with_target("cdn77", level);
+	let subscriber = tracing_subscriber::fmt()
+		.with_writer(io::stderr)
+		.with_ansi(io::stderr().is_terminal())
+		.with_max_level(level);
+	match format {
+		LogFormat::Text => subscriber.finish().with(filter).init(),
+		LogFormat::Json => subscriber.json().finish().with(filter).init(),
+	}
+}
+
+/// The error text of clap on a single line, e.g. including the missing args, but without the usage and help hint
+fn clap_error_message(err: &clap::Error) -> String {
+	err.to_string()
+		.lines()
+		.take_while(|line| !line.starts_with("USAGE:") && !line.starts_with("For more information"))
+		.map(|line| line.trim())
+		.filter(|line| !line.is_empty())
+		.collect::<Vec<_>>()
+		.join(" ")
+		.trim_start_matches("error: ")
+		.to_string()
+}
+
+/// When the args can't be parsed, the error format has to be picked from the raw args
+fn error_format_from_args() -> ErrorFormat {
+	let args: Vec<String> = env::args_os().map(|arg| arg.to_string_lossy().into_owned()).collect();
+	error_format_from(&args, env::var("CDN77_ERROR_FORMAT").ok())
+}
+
+/// The value of `--error-format`, given as one or two args, otherwise the one of the env variable
+fn error_format_from(args: &[String], env_format: Option<String>) -> ErrorFormat {
+	let arg = args.iter()
+		.enumerate()
+		.find_map(|(i, arg)| match arg.strip_prefix("--error-format") {
+			Some("") => args.get(i + 1).cloned(),
+			Some(value) => value.strip_prefix('=').map(|v| v.to_string()),
+			None => None,
+		});
+
+	arg.or(env_format)
+		.and_then(|format| format.parse().ok())
+		.unwrap_or(ErrorFormat::Text)
+}
+
+async fn run_command(cli_opts: &CliOpts) -> Result<(), CliError> {
+	// These only depend on the arg definitions, so they work without a token or config
 	match &cli_opts.command {
+		RootCommands::Completions { shell } => return command_completions(CliOpts::command(), *shell),
+		RootCommands::Man { dir } => return command_man(CliOpts::command(), dir),
+		_ => {}
+	}
+
+	let profile = Config::load(cli_opts.config.as_deref())?.profile(cli_opts.profile.as_deref())?;
+	let api_token = resolve_api_token(cli_opts, &profile)?;
+	let client = create_cdn77_client(cli_opts, &profile, &api_token)?;
+	let output = Output::new(cli_opts.output.or(profile.output()?).unwrap_or(OutputFormat::Text));
+	let resource_cache_ttl = cli_opts.resource_cache_ttl.or(profile.resource_cache_ttl).unwrap_or(DEFAULT_RESOURCE_CACHE_TTL_SECS);
+	let resolver = ResourceResolver::new(&client, &api_token, Duration::from_secs(resource_cache_ttl));
+
+	let result = match &cli_opts.command {
 		RootCommands::Billing(command) => {
 			match &command {
-				BillingCommands::CreditBalance {} => {
-					command_billing_get_credit_balance(client).await;
+				BillingCommands::CreditBalance => {
+					command_billing_get_credit_balance(&client, &output).await
 				}
 			}
 		}
+		RootCommands::Completions { .. } | RootCommands::Man { .. } => unreachable!("Handled before the client is created"),
 		RootCommands::Jobs(command) => {
 			match &command {
 				JobsCommands::List { resource_id, job_type } => {
-					command_jobs_list(client, resource_id, job_type).await;
+					command_jobs_list(&client, &output, &resolver.resource_id(resource_id, &profile).await?, job_type).await
+				}
+				JobsCommands::Watch { resource_ids, job_types, interval, until_done } => {
+					let resource_ids = resolver.resource_ids(resource_ids, &profile).await?
+						.ok_or_else(|| CliError::InvalidInput("No resource IDs given, use --resource-ids or set resource_ids in the profile".to_string()))?;
+					command_jobs_watch(&client, &output, &resource_ids, job_types, *interval, *until_done).await
 				}
 				JobsCommands::Detail { resource_id, job_id } => {
-					command_jobs_detail(client, resource_id, job_id).await;
+					command_jobs_detail(&client, &output, &resolver.resource_id(resource_id, &profile).await?, job_id).await
+				}
+				JobsCommands::Prefetch { resource_id, paths, upstream_host, batch, wait } => {
+					command_jobs_prefetch(&client, &output, &resolver.resource_id(resource_id, &profile).await?, paths, upstream_host, batch, wait).await
+				}
+				JobsCommands::PrefetchSitemap { resource_id, sitemap, upstream_host, batch, wait } => {
+					command_jobs_prefetch_sitemap(&client, &output, &resolver, &resolver.resource_id(resource_id, &profile).await?, sitemap, upstream_host, batch, wait).await
+				}
+				JobsCommands::PrefetchDir { resource_id, dir, upstream_host, batch, wait } => {
+					command_jobs_prefetch_dir(&client, &output, &resolver.resource_id(resource_id, &profile).await?, dir, upstream_host, batch, wait).await
+				}
+				JobsCommands::Purge { urls, batch, wait, .. } if !urls.is_empty() => {
+					command_jobs_purge_urls(&client, &output, &resolver, urls, batch, wait).await
+				}
+				JobsCommands::Purge { resource_id, paths, batch, wait, .. } => {
+					command_jobs_purge(&client, &output, &resolver.resource_id(resource_id, &profile).await?, paths, batch, wait).await
 				}
-				JobsCommands::Prefetch { resource_id, paths, upstream_host } => {
-					command_jobs_prefetch(client, resource_id, paths, upstream_host).await;
+				JobsCommands::PurgeDir { resource_id, dir, batch, wait } => {
+					command_jobs_purge_dir(&client, &output, &resolver.resource_id(resource_id, &profile).await?, dir, batch, wait).await
 				}
-				JobsCommands::Purge { resource_id, paths } => {
-					command_jobs_purge(client, resource_id, paths).await;
+				JobsCommands::PurgeGit { resource_id, git, batch, wait } => {
+					command_jobs_purge_git(&client, &output, &resolver.resource_id(resource_id, &profile).await?, git, batch, wait, cli_opts.dry_run).await
 				}
-				JobsCommands::PurgeAll { resource_id } => {
-					command_jobs_purge_all(client, resource_id).await;
+				JobsCommands::PurgeAll { resource_id, yes, force_protected, wait } => {
+					let confirm = !*yes && !cli_opts.dry_run;
+					let resource_id = resolver.resource_id(resource_id, &profile).await?;
+					let protected_resource_ids = resolver.resolve_all(&profile.protected_resource_ids).await?;
+					command_jobs_purge_all(&client, &output, &resource_id, &protected_resource_ids, *force_protected, confirm, wait).await
 				}
 			}
 		}
@@ -328,67 +556,198 @@ async fn main() {
 			panic!("RawLog isn't implemented yet! {:?}", command);
 		}
 		RootCommands::Resources(command) => {
-			// TODO Implement https://client.cdn77.com/support/api-reference/v3/cdn-resources
-			panic!("Origin isn't implemented yet! {:?}", command);
+			match &command {
+				ResourcesCommands::List => {
+					command_resources_list(&client, &output).await
+				}
+			}
 		}
 		RootCommands::Statistics(command) => {
 			match &command {
-				StatisticsCommands::Stats { stat_type, from, to, resource_ids, location_ids, aggregation, } => {
-					command_stats_get_stats(client, stat_type, from, to, resource_ids, location_ids, aggregation).await;
+				StatisticsCommands::Stats { stat_type, filter, aggregation, } => {
+					command_stats_get_stats(&client, &output, stat_type, &filter.parse(&resolver, &profile).await?, aggregation).await
 				}
-				StatisticsCommands::Bandwidth95Percentile { from, to, resource_ids, location_ids } => {
-					command_stats_bandwidth_95th_percentile(client, from, to, resource_ids, location_ids).await;
+				StatisticsCommands::Bandwidth95Percentile { filter } => {
+					command_stats_bandwidth_95th_percentile(&client, &output, &filter.parse(&resolver, &profile).await?).await
 				}
-				StatisticsCommands::StatsByResource { stat_type, from, to, resource_ids, location_ids, aggregation } => {
-					command_stats_by_resource(client, stat_type, from, to, resource_ids, location_ids, aggregation).await;
+				StatisticsCommands::StatsByResource { stat_type, filter, aggregation } => {
+					command_stats_by_resource(&client, &output, stat_type, &filter.parse(&resolver, &profile).await?, aggregation).await
 				}
-				StatisticsCommands::SumByResource { stat_type, from, to, resource_ids, location_ids } => {
-					command_stats_sum_by_resource(client, stat_type, from, to, resource_ids, location_ids).await;
+				StatisticsCommands::SumByResource { stat_type, filter } => {
+					command_stats_sum_by_resource(&client, &output, stat_type, &filter.parse(&resolver, &profile).await?).await
 				}
-				StatisticsCommands::StatsByDataCenter { stat_type, from, to, resource_ids, location_ids, aggregation } => {
-					command_stats_by_data_center(client, stat_type, from, to, resource_ids, location_ids, aggregation).await;
+				StatisticsCommands::StatsByDataCenter { stat_type, filter, aggregation } => {
+					command_stats_by_data_center(&client, &output, stat_type, &filter.parse(&resolver, &profile).await?, aggregation).await
 				}
-				StatisticsCommands::SumByDataCenter { stat_type, from, to, resource_ids, location_ids } => {
-					command_stats_sum_by_data_center(client, stat_type, from, to, resource_ids, location_ids).await;
+				StatisticsCommands::SumByDataCenter { stat_type, filter } => {
+					command_stats_sum_by_data_center(&client, &output, stat_type, &filter.parse(&resolver, &profile).await?).await
 				}
-				StatisticsCommands::Sum { stat_type, from, to, resource_ids, location_ids } => {
-					command_stats_sum(client, stat_type, from, to, resource_ids, location_ids).await;
+				StatisticsCommands::Sum { stat_type, filter } => {
+					command_stats_sum(&client, &output, stat_type, &filter.parse(&resolver, &profile).await?).await
 				}
 			}
 		}
 		RootCommands::Storage(command) => {
 			match &command {
 				StorageCommands::List => {
-					command_storage_list(client).await;
+					command_storage_list(&client, &output).await
 				}
 				StorageCommands::Detail { storage_id } => {
-					command_storage_detail(client, storage_id).await;
+					command_storage_detail(&client, &output, storage_id).await
 				}
 			}
 		}
+	};
+
+	match result {
+		Err(CliError::Api { error: Cdn77Error::DryRun(request), .. }) => {
+			output.render(&request, print_dry_run_request);
+			Ok(())
+		}
+		result => result,
 	}
 }
 
-fn create_cdn77_client(cli_opts: &CliOpts) -> Client {
-	let token = match &cli_opts.api_token {
-		Some(t) => t.to_string(),
-		_ => match env::var("CDN77_API_TOKEN") {
-			Ok(t) => t,
-			Err(_) => {
-				eprintln!("No API token detected, please specify one either in the arguments or via env");
-				std::process::exit(EXIT_CODE_INVALID_INPUT);
-			}
+/// Only one of the token args may be used, it's unclear which one is intended otherwise
+fn api_token_from_args(cli_opts: &CliOpts) -> Result<Option<String>, CliError> {
+	// The token would consume all of stdin, leaving no paths
+	if cli_opts.api_token_stdin && cli_opts.command.reads_stdin() {
+		return Err(CliError::InvalidInput("--api-token-stdin can't be combined with --paths-file -, as both read stdin".to_string()));
+	}
+
+	match (&cli_opts.api_token, &cli_opts.api_token_file, cli_opts.api_token_stdin) {
+		(Some(token), None, false) => Ok(Some(token.to_string())),
+		(None, Some(file), false) => read_token_file(file).map(Some),
+		(None, None, true) => read_token_stdin().map(Some),
+		(None, None, false) => Ok(None),
+		_ => Err(CliError::InvalidInput("Conflicting API token sources, only use one of --api-token, --api-token-file and --api-token-stdin".to_string())),
+	}
+}
+
+/// Like all settings, the token is taken from the args first, then from env variables and finally from the profile of the config file.
+/// An explicitly selected profile's token wins over `CDN77_API_TOKEN` though, which might belong to another account, e.g. loaded from `.env`.
+fn resolve_api_token(cli_opts: &CliOpts, profile: &Profile) -> Result<String, CliError> {
+	if let Some(token) = api_token_from_args(cli_opts)? {
+		return Ok(token);
+	}
+
+	let token = match cli_opts.profile {
+		Some(_) => profile.api_token()?.or_else(|| env::var("CDN77_API_TOKEN").ok()),
+		None => match env::var("CDN77_API_TOKEN") {
+			Ok(token) => Some(token),
+			Err(_) => profile.api_token()?,
 		},
 	};
 
-	let mut default_headers = header::HeaderMap::new();
-	let token = format!("Bearer {}", &token);
-	default_headers.insert(header::AUTHORIZATION, header::HeaderValue::from_str(token.as_str()).unwrap());
-	default_headers.append(header::USER_AGENT,
-						   header::HeaderValue::from_str(USER_AGENT).unwrap());
+	match token {
+		Some(token) => Ok(token),
+		// Replayed requests never reach the API, so the token doesn't matter
+		None if cli_opts.replay.is_some() => Ok("replay".to_string()),
+		None => Err(CliError::InvalidInput("No API token detected, please specify one either in the arguments, via env or in the config profile".to_string())),
+	}
+}
+
+/// Settings are taken from the args first, then from env variables and finally from the profile of the config file
+fn create_cdn77_client(cli_opts: &CliOpts, profile: &Profile, token: &str) -> Result<Cdn77Client, CliError> {
+	let api_base = cli_opts.api_base.as_deref().or(profile.api_base.as_deref()).unwrap_or(CDN77_API_BASE);
+	let timeout = cli_opts.timeout.or(profile.timeout).unwrap_or(DEFAULT_TIMEOUT_SECS);
+	let connect_timeout = cli_opts.connect_timeout.or(profile.connect_timeout).unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS);
 
-	Client::builder()
-		.default_headers(default_headers)
-		.build()
-		.unwrap_or_else(|err| panic!("Failed to create Reqwuest client: {:?}", err))
+	let mut builder = Cdn77Client::builder(token)
+		.api_base(api_base)
+		.retries(cli_opts.retries)
+		.retry_max_wait(Duration::from_secs(cli_opts.retry_max_wait))
+		.timeout(Duration::from_secs(timeout))
+		.connect_timeout(Duration::from_secs(connect_timeout))
+		.dry_run(cli_opts.dry_run);
+
+	if let Some(proxy) = cli_opts.proxy.as_ref().or(profile.proxy.as_ref()) {
+		builder = builder.proxy(proxy);
+	}
+	if let Some(ca_cert) = cli_opts.ca_cert.as_ref().or(profile.ca_cert.as_ref()) {
+		builder = builder.ca_cert_file(ca_cert);
+	}
+	if let Some(rate_limit) = cli_opts.rate_limit {
+		builder = builder.rate_limit(rate_limit);
+	}
+	if let Some(dir) = &cli_opts.record {
+		builder = builder.record_fixtures(dir);
+	}
+	if let Some(dir) = &cli_opts.replay {
+		builder = builder.replay_fixtures(dir);
+	}
+
+	Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn cli_opts(args: &[&str]) -> CliOpts {
+		CliOpts::try_parse_from(["cdn77-client"].iter().chain(args).chain(&["resources", "list"])).unwrap()
+	}
+
+	fn args(args: &[&str]) -> Vec<String> {
+		args.iter().map(|arg| arg.to_string()).collect()
+	}
+
+	#[test]
+	fn error_format_from_separate_or_joined_value() {
+		assert_eq!(error_format_from(&args(&["cdn77-client", "--error-format=json", "jobs"]), None), ErrorFormat::Json);
+		assert_eq!(error_format_from(&args(&["cdn77-client", "jobs", "--error-format", "json"]), None), ErrorFormat::Json);
+		assert_eq!(error_format_from(&args(&["cdn77-client", "--error-format", "text"]), Some("json".to_string())), ErrorFormat::Text);
+	}
+
+	#[test]
+	fn error_format_from_env_or_text() {
+		assert_eq!(error_format_from(&args(&["cdn77-client", "jobs"]), Some("json".to_string())), ErrorFormat::Json);
+		assert_eq!(error_format_from(&args(&["cdn77-client", "jobs"]), None), ErrorFormat::Text);
+		// Invalid or missing values can't be reported in the requested format
+		assert_eq!(error_format_from(&args(&["cdn77-client", "--error-format=xml"]), None), ErrorFormat::Text);
+		assert_eq!(error_format_from(&args(&["cdn77-client", "--error-format"]), None), ErrorFormat::Text);
+	}
+
+	#[test]
+	fn clap_error_message_is_a_single_line() {
+		let err = match CliOpts::try_parse_from(["cdn77-client", "jobs", "detail", "-i", "12345"]) {
+			Err(err) => err,
+			Ok(_) => panic!("The missing --job-id isn't an error"),
+		};
+
+		let message = clap_error_message(&err);
+
+		assert_eq!(message, "The following required arguments were not provided: --job-id <JOB_ID>");
+	}
+
+	#[test]
+	fn api_token_from_args_rejects_conflicting_sources() {
+		let conflicts: [&[&str]; 3] = [
+			&["-a", "token", "--api-token-file", "token.txt"],
+			&["-a", "token", "--api-token-stdin"],
+			&["--api-token-file", "token.txt", "--api-token-stdin"],
+		];
+		for args in conflicts {
+			match api_token_from_args(&cli_opts(args)) {
+				Err(CliError::InvalidInput(message)) => assert!(message.starts_with("Conflicting API token sources"), "{}", message),
+				result => panic!("Unexpected result for {:?}: {:?}", args, result),
+			}
+		}
+	}
+
+	#[test]
+	fn api_token_from_args_uses_the_single_source() {
+		assert_eq!(api_token_from_args(&cli_opts(&["-a", "token"])).unwrap().as_deref(), Some("token"));
+		assert_eq!(api_token_from_args(&cli_opts(&[])).unwrap(), None);
+	}
+
+	#[test]
+	fn resolve_api_token_rejects_conflicting_profile_sources() {
+		let profile = Profile { api_token: Some("token".to_string()), token_command: Some("echo token".to_string()), ..Profile::default() };
+
+		match resolve_api_token(&cli_opts(&["--profile", "prod"]), &profile) {
+			Err(CliError::InvalidInput(message)) => assert!(message.contains("only set one of"), "{}", message),
+			result => panic!("Unexpected result {:?}", result),
+		}
+	}
 }
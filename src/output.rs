@@ -0,0 +1,289 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Format of the command results on stdout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+	Text,
+	Json,
+	Yaml,
+	Table,
+	Csv,
+}
+
+impl FromStr for OutputFormat {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"text" => Ok(OutputFormat::Text),
+			"json" => Ok(OutputFormat::Json),
+			"yaml" => Ok(OutputFormat::Yaml),
+			"table" => Ok(OutputFormat::Table),
+			"csv" => Ok(OutputFormat::Csv),
+			_ => Err("Invalid output format, use one of: json, yaml, table, csv, text"),
+		}
+	}
+}
+
+/// Format of error messages on stderr
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorFormat {
+	Text,
+	Json,
+}
+
+impl FromStr for ErrorFormat {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"text" => Ok(ErrorFormat::Text),
+			"json" => Ok(ErrorFormat::Json),
+			_ => Err("Invalid error format, use one of: json, text"),
+		}
+	}
+}
+
+/// Format of the `-v` HTTP logs on stderr
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+	Text,
+	Json,
+}
+
+impl FromStr for LogFormat {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"text" => Ok(LogFormat::Text),
+			"json" => Ok(LogFormat::Json),
+			_ => Err("Invalid log format, use one of: json, text"),
+		}
+	}
+}
+
+/// Renders command results, only the results go to stdout for machine-readable formats so they can be piped
+#[derive(Clone, Copy, Debug)]
+pub struct Output {
+	format: OutputFormat,
+}
+
+impl Output {
+	pub fn new(format: OutputFormat) -> Self {
+		Output { format }
+	}
+
+	/// Progress and informational messages, these go to stderr unless the output is text
+	pub fn info(&self, message: impl Display) {
+		match self.format {
+			OutputFormat::Text => println!("{}", message),
+			_ => eprintln!("{}", message),
+		}
+	}
+
+	/// Renders the result, the text format is up to the command
+	pub fn render<T: Serialize>(&self, data: &T, text: impl FnOnce(&T)) {
+		match self.format {
+			OutputFormat::Text => text(data),
+			OutputFormat::Json => println!("{}", serde_json::to_string_pretty(data).expect("Output models are serializable")),
+			OutputFormat::Yaml => print!("{}", serde_yaml::to_string(data).expect("Output models are serializable")),
+			OutputFormat::Table => {
+				let (columns, rows) = to_rows(&serde_json::to_value(data).expect("Output models are serializable"));
+				print_table(&columns, &rows);
+			}
+			OutputFormat::Csv => {
+				let (columns, rows) = to_rows(&serde_json::to_value(data).expect("Output models are serializable"));
+				println!("{}", columns.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(","));
+				for row in rows {
+					println!("{}", row.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(","));
+				}
+			}
+		}
+	}
+
+	/// Renders one refresh of a repeatedly updated result like [`Output::render`], but JSON as one compact line, so it can be read line by line
+	pub fn render_line<T: Serialize>(&self, data: &T, text: impl FnOnce(&T)) {
+		match self.format {
+			OutputFormat::Json => println!("{}", serde_json::to_string(data).expect("Output models are serializable")),
+			_ => self.render(data, text),
+		}
+	}
+}
+
+/// Flattens a value into columns and rows. Lists become one row per entry, maps of objects (e.g. stats per resource) one row per key.
+fn to_rows(value: &Value) -> (Vec<String>, Vec<Vec<String>>) {
+	let records: Vec<(Option<&String>, &Value)> = match value {
+		Value::Array(items) => items.iter().map(|i| (None, i)).collect(),
+		Value::Object(map) if !map.is_empty() && map.values().all(|v| v.is_object()) => map.iter().map(|(k, v)| (Some(k), v)).collect(),
+		value => vec![(None, value)],
+	};
+
+	let records: Vec<(Option<&String>, Value)> = records.into_iter().map(|(key, record)| (key, flatten(record))).collect();
+
+	let mut columns: Vec<String> = vec![];
+	if records.iter().any(|(key, _)| key.is_some()) {
+		columns.push("key".to_string());
+	}
+	for (_, record) in &records {
+		match record {
+			Value::Object(map) => {
+				for key in map.keys() {
+					if !columns.contains(key) {
+						columns.push(key.to_string());
+					}
+				}
+			}
+			_ => {
+				if !columns.iter().any(|c| c == "value") {
+					columns.push("value".to_string());
+				}
+			}
+		}
+	}
+
+	let rows = records.iter()
+		.map(|(key, record)| columns.iter()
+			.map(|column| match (column.as_str(), key, record) {
+				("key", Some(key), _) => key.to_string(),
+				(column, _, Value::Object(map)) => map.get(column).map(cell).unwrap_or_default(),
+				("value", _, value) => cell(value),
+				_ => String::new(),
+			})
+			.collect())
+		.collect();
+
+	(columns, rows)
+}
+
+/// Nested objects become dotted columns, e.g. `cdn.id`
+fn flatten(value: &Value) -> Value {
+	fn add(prefix: &str, value: &Value, into: &mut Map<String, Value>) {
+		match value {
+			Value::Object(map) => map.iter().for_each(|(k, v)| add(&format!("{}{}.", prefix, k), v, into)),
+			value => {
+				into.insert(prefix.trim_end_matches('.').to_string(), value.clone());
+			}
+		}
+	}
+
+	match value {
+		Value::Object(_) => {
+			let mut map = Map::new();
+			add("", value, &mut map);
+			Value::Object(map)
+		}
+		value => value.clone(),
+	}
+}
+
+fn cell(value: &Value) -> String {
+	match value {
+		Value::Null => String::new(),
+		Value::String(s) => s.to_string(),
+		Value::Array(items) if items.iter().all(|i| !i.is_array() && !i.is_object()) => items.iter().map(cell).collect::<Vec<_>>().join(";"),
+		value => value.to_string(),
+	}
+}
+
+fn print_table(columns: &[String], rows: &[Vec<String>]) {
+	format_table(columns, rows).iter().for_each(|line| println!("{}", line));
+}
+
+/// The lines of a table with aligned columns: the upper-cased header, a separator and one line per row
+pub fn format_table(columns: &[String], rows: &[Vec<String>]) -> Vec<String> {
+	let widths: Vec<usize> = columns.iter()
+		.enumerate()
+		.map(|(i, c)| rows.iter().map(|r| r[i].chars().count()).chain([c.chars().count()]).max().unwrap_or(0))
+		.collect();
+	let format_row = |row: &[String]| row.iter()
+		.zip(&widths)
+		.map(|(c, w)| format!("{:<width$}", c, width = w))
+		.collect::<Vec<_>>()
+		.join("  ")
+		.trim_end()
+		.to_string();
+
+	let header = format_row(&columns.iter().map(|c| c.to_uppercase()).collect::<Vec<_>>());
+	let separator = widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("  ");
+	[header, separator].into_iter().chain(rows.iter().map(|row| format_row(row))).collect()
+}
+
+fn csv_field(value: &str) -> String {
+	if value.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", value.replace('"', "\"\""))
+	} else {
+		value.to_string()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+
+	fn strings(values: &[&str]) -> Vec<String> {
+		values.iter().map(|v| v.to_string()).collect()
+	}
+
+	#[test]
+	fn to_rows_makes_one_row_per_list_entry() {
+		let (columns, rows) = to_rows(&json!([
+			{ "id": 1, "state": "done", "paths": ["/a", "/b"] },
+			{ "id": 2, "state": "queued", "done_at": null },
+		]));
+
+		// The keys of JSON objects are sorted, the columns of later entries are appended
+		assert_eq!(columns, strings(&["id", "paths", "state", "done_at"]));
+		assert_eq!(rows, [strings(&["1", "/a;/b", "done", ""]), strings(&["2", "", "queued", ""])]);
+	}
+
+	#[test]
+	fn to_rows_makes_one_row_per_key_of_a_map_of_objects() {
+		let (columns, rows) = to_rows(&json!({
+			"12345": { "hit": 10, "miss": 2 },
+			"67890": { "hit": 5 },
+		}));
+
+		assert_eq!(columns, strings(&["key", "hit", "miss"]));
+		assert_eq!(rows, [strings(&["12345", "10", "2"]), strings(&["67890", "5", ""])]);
+	}
+
+	#[test]
+	fn to_rows_flattens_nested_objects_into_dotted_columns() {
+		let (columns, rows) = to_rows(&json!({ "id": "job-1", "cdn": { "id": 12345, "origin": { "host": "example.com" } }, "count": 3 }));
+
+		assert_eq!(columns, strings(&["cdn.id", "cdn.origin.host", "count", "id"]));
+		assert_eq!(rows, [strings(&["12345", "example.com", "3", "job-1"])]);
+	}
+
+	#[test]
+	fn to_rows_uses_a_value_column_for_scalars() {
+		let (columns, rows) = to_rows(&json!(["/a", "/b"]));
+
+		assert_eq!(columns, strings(&["value"]));
+		assert_eq!(rows, [strings(&["/a"]), strings(&["/b"])]);
+		assert_eq!(to_rows(&json!(42)), (strings(&["value"]), vec![strings(&["42"])]));
+	}
+
+	#[test]
+	fn csv_field_quotes_fields_with_special_characters() {
+		assert_eq!(csv_field("plain"), "plain");
+		assert_eq!(csv_field("a,b"), "\"a,b\"");
+		assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+		assert_eq!(csv_field("line\nbreak"), "\"line\nbreak\"");
+		assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+	}
+
+	#[test]
+	fn format_table_aligns_the_columns() {
+		let lines = format_table(&strings(&["id", "state"]), &[strings(&["1", "done"]), strings(&["12345", "ü"])]);
+
+		assert_eq!(lines, ["ID     STATE", "-----  -----", "1      done", "12345  ü"]);
+	}
+}
@@ -0,0 +1,444 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use reqwest::Url;
+
+use crate::util::CliError;
+
+/// Replaces a path prefix, e.g. `/static/=/assets/` if the files of `static` are served under `/assets`
+#[derive(Clone, Debug)]
+pub struct PathRewrite {
+	from: String,
+	to: String,
+}
+
+impl FromStr for PathRewrite {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.split_once('=') {
+			Some((from, to)) if from.starts_with('/') && to.starts_with('/') => Ok(PathRewrite { from: from.to_string(), to: to.to_string() }),
+			_ => Err(format!("Invalid rewrite rule '{}', use FROM=TO with both starting with /", s)),
+		}
+	}
+}
+
+/// The files changed between two revisions relative to the repository root. Renames are split into the deleted and the added file,
+/// as both paths are outdated in the cache.
+pub fn git_changed_files(repo: &Path, from: &str, to: &str) -> Result<Vec<String>, CliError> {
+	// git would take these as options, e.g. `--output=FILE`, and the diff would silently be empty
+	if let Some(revision) = [from, to].into_iter().find(|r| r.starts_with('-')) {
+		return Err(CliError::InvalidInput(format!("Invalid git revision '{}', revisions can't start with -", revision)));
+	}
+
+	let output = Command::new("git")
+		.arg("-C")
+		.arg(repo)
+		.args(["diff", "--name-only", "--no-renames", "-z", "--end-of-options", from, to, "--"])
+		.output()
+		.map_err(|err| CliError::InvalidInput(format!("Cannot run git: {}", err)))?;
+
+	if !output.status.success() {
+		return Err(CliError::InvalidInput(format!("git diff {}..{} failed: {}", from, to, String::from_utf8_lossy(&output.stderr).trim())));
+	}
+
+	Ok(String::from_utf8_lossy(&output.stdout)
+		.split('\0')
+		.filter(|file| !file.is_empty())
+		.map(|file| file.to_string())
+		.collect())
+}
+
+/// Maps files relative to the repository to encoded CDN paths, files outside of the web root are skipped.
+/// The first matching rewrite rule is applied, duplicates caused by the rules are removed.
+pub fn files_to_cdn_paths(files: &[String], web_root: &str, rewrites: &[PathRewrite]) -> Vec<String> {
+	let web_root = normalize_web_root(web_root);
+	let mut paths: Vec<String> = vec![];
+	let mut seen = HashSet::new();
+
+	for file in files {
+		let relative = match web_root {
+			"" => Some(file.as_str()),
+			web_root => file.strip_prefix(web_root).and_then(|f| f.strip_prefix('/')),
+		};
+		let path = match relative {
+			Some(relative) => format!("/{}", relative),
+			None => continue,
+		};
+		let path = rewrites.iter()
+			.find_map(|r| path.strip_prefix(&r.from).map(|rest| format!("{}{}", r.to, rest)))
+			.unwrap_or(path);
+		let path = encode_path(&path);
+
+		if seen.insert(path.to_string()) {
+			paths.push(path);
+		}
+	}
+
+	paths
+}
+
+/// The web root relative to the repository without leading or trailing slashes, e.g. `./public/` as `public`
+fn normalize_web_root(web_root: &str) -> &str {
+	let mut web_root = web_root.trim_matches('/');
+	while let Some(rest) = web_root.strip_prefix("./") {
+		web_root = rest.trim_start_matches('/');
+	}
+	match web_root {
+		"." => "",
+		web_root => web_root,
+	}
+}
+
+/// The `<loc>` URLs of a sitemap. Sitemap indexes are followed to their nested sitemaps, which must be local files as well.
+pub fn sitemap_urls(file: &Path) -> Result<Vec<String>, CliError> {
+	let mut urls = vec![];
+	read_sitemap(file, &mut urls, &mut HashSet::new())?;
+	Ok(urls)
+}
+
+fn read_sitemap(file: &Path, urls: &mut Vec<String>, visited: &mut HashSet<PathBuf>) -> Result<(), CliError> {
+	// Indexes referencing each other would recurse forever otherwise
+	if !visited.insert(file.canonicalize().unwrap_or_else(|_| file.to_path_buf())) {
+		return Ok(());
+	}
+
+	let content = fs::read_to_string(file)
+		.map_err(|err| CliError::InvalidInput(format!("Cannot read sitemap {}: {}", file.display(), err)))?;
+	let document = roxmltree::Document::parse(&content)
+		.map_err(|err| CliError::InvalidInput(format!("Invalid sitemap {}: {}", file.display(), err)))?;
+	let root = document.root_element();
+	let locs = |entry: &'static str| root.children()
+		.filter(move |n| n.has_tag_name(entry))
+		.filter_map(|n| n.children().find(|c| c.has_tag_name("loc")))
+		.filter_map(|loc| loc.text())
+		.map(|loc| loc.trim().to_string());
+
+	match root.tag_name().name() {
+		"urlset" => urls.extend(locs("url")),
+		"sitemapindex" => {
+			for loc in locs("sitemap").collect::<Vec<_>>() {
+				read_sitemap(&nested_sitemap_file(file, &loc)?, urls, visited)?;
+			}
+		}
+		other => return Err(CliError::InvalidInput(format!("{} is no sitemap, its root element is <{}>", file.display(), other))),
+	}
+
+	Ok(())
+}
+
+/// Nested sitemaps are looked up relative to the index by their URL path, e.g. `https://example.com/sitemaps/pages.xml` as
+/// `sitemaps/pages.xml`, and then by their file name
+fn nested_sitemap_file(index: &Path, loc: &str) -> Result<PathBuf, CliError> {
+	let dir = index.parent().unwrap_or_else(|| Path::new("."));
+	let path = Url::parse(loc).map(|url| url.path().to_string()).unwrap_or_else(|_| loc.to_string());
+	let by_path = dir.join(path.trim_start_matches('/'));
+	let by_name = Path::new(&path).file_name().map(|name| dir.join(name));
+
+	[Some(by_path), by_name].into_iter()
+		.flatten()
+		.find(|file| file.is_file())
+		.ok_or_else(|| CliError::InvalidInput(format!("The nested sitemap {} of {} isn't a local file next to it", loc, index.display())))
+}
+
+/// The paths of the URLs, which is what the CDN caches by. Duplicates are removed.
+pub fn urls_to_paths(urls: &[String]) -> Result<Vec<String>, CliError> {
+	let mut paths: Vec<String> = vec![];
+	let mut seen = HashSet::new();
+
+	for url in urls {
+		let path = url_path(&Url::parse(url).map_err(|err| CliError::InvalidInput(format!("Invalid URL {}: {}", url, err)))?);
+		if seen.insert(path.to_string()) {
+			paths.push(path);
+		}
+	}
+
+	Ok(paths)
+}
+
+/// A file found by [`dir_files`]
+pub struct DirFile {
+	/// Relative to the walked dir, separated by `/` on all platforms
+	pub relative_path: String,
+	pub size: u64,
+}
+
+/// All files below the dir, sorted by path. Symlinked dirs aren't followed, as they might form a loop.
+pub fn dir_files(dir: &Path) -> Result<Vec<DirFile>, CliError> {
+	fn walk(dir: &Path, prefix: &str, files: &mut Vec<DirFile>) -> Result<(), CliError> {
+		let entries = fs::read_dir(dir).map_err(|err| CliError::InvalidInput(format!("Cannot read dir {}: {}", dir.display(), err)))?;
+		for entry in entries {
+			let entry = entry.map_err(|err| CliError::InvalidInput(format!("Cannot read dir {}: {}", dir.display(), err)))?;
+			let path = entry.path();
+			let relative_path = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+			let file_type = entry.file_type().map_err(|err| CliError::InvalidInput(format!("Cannot read {}: {}", path.display(), err)))?;
+
+			if file_type.is_dir() {
+				walk(&path, &format!("{}/", relative_path), files)?;
+			} else if let Some(metadata) = fs::metadata(&path).ok().filter(|m| m.is_file()) {
+				files.push(DirFile { relative_path, size: metadata.len() });
+			}
+		}
+		Ok(())
+	}
+
+	let mut files = vec![];
+	walk(dir, "", &mut files)?;
+	files.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+	Ok(files)
+}
+
+/// The unencoded CDN paths of a file served under the URL prefix, which gets a leading `/` if it's missing. An `index.html` is served as its
+/// dir, e.g. `blog/index.html` as `/blog/`. With `with_index_file`, it's also returned as `/blog/index.html`, as both URLs are cached.
+pub fn dir_file_to_cdn_paths(url_prefix: &str, relative_path: &str, with_index_file: bool) -> Vec<String> {
+	let url_prefix = match url_prefix.trim_matches('/') {
+		"" => "/".to_string(),
+		url_prefix => format!("/{}/", url_prefix),
+	};
+	let dir = match relative_path.rsplit_once('/') {
+		Some((dir, "index.html")) => Some(format!("{}/", dir)),
+		None if relative_path == "index.html" => Some(String::new()),
+		_ => None,
+	};
+
+	match dir {
+		Some(dir) if with_index_file => vec![format!("{}{}", url_prefix, dir), format!("{}{}", url_prefix, relative_path)],
+		Some(dir) => vec![format!("{}{}", url_prefix, dir)],
+		None => vec![format!("{}{}", url_prefix, relative_path)],
+	}
+}
+
+/// Parses sizes like `512`, `100K`, `10M` or `1G`, the units are binary
+pub fn parse_size(s: &str) -> Result<u64, String> {
+	let s = s.trim();
+	let (number, factor) = match s.char_indices().last() {
+		Some((i, 'K' | 'k')) => (&s[..i], 1 << 10),
+		Some((i, 'M' | 'm')) => (&s[..i], 1 << 20),
+		Some((i, 'G' | 'g')) => (&s[..i], 1 << 30),
+		_ => (s, 1),
+	};
+
+	number.parse::<u64>()
+		.ok()
+		.and_then(|n| n.checked_mul(factor))
+		.ok_or_else(|| format!("Invalid size '{}', use e.g. 512, 100K, 10M or 1G", s))
+}
+
+/// The encoded path and query of a URL
+pub fn url_path(url: &Url) -> String {
+	match url.query() {
+		Some(query) => format!("{}?{}", url.path(), query),
+		None => url.path().to_string(),
+	}
+}
+
+/// Percent-encodes everything but the characters allowed in URL paths. `*` is encoded as well, as the API treats it as a wildcard.
+pub fn encode_path(path: &str) -> String {
+	path.bytes()
+		.map(|b| match b {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'+' | b','
+			| b';' | b'=' | b':' | b'@' => (b as char).to_string(),
+			b => format!("%{:02X}", b),
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn files(files: &[&str]) -> Vec<String> {
+		files.iter().map(|f| f.to_string()).collect()
+	}
+
+	fn rewrites(rules: &[&str]) -> Vec<PathRewrite> {
+		rules.iter().map(|r| r.parse().unwrap()).collect()
+	}
+
+	/// An empty dir per test with the given files
+	fn temp_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("cdn77-path-sources-test-{}-{}", std::process::id(), name));
+		let _ = fs::remove_dir_all(&dir);
+		for (file, content) in files {
+			let file = dir.join(file);
+			fs::create_dir_all(file.parent().unwrap()).unwrap();
+			fs::write(file, content).unwrap();
+		}
+		dir
+	}
+
+	fn urlset(urls: &[&str]) -> String {
+		let urls: String = urls.iter().map(|u| format!("<url><loc> {} </loc><lastmod>2022-06-01</lastmod></url>", u)).collect();
+		format!(r#"<?xml version="1.0" encoding="UTF-8"?><urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">{}</urlset>"#, urls)
+	}
+
+	fn sitemap_index(locs: &[&str]) -> String {
+		let locs: String = locs.iter().map(|l| format!("<sitemap><loc>{}</loc></sitemap>", l)).collect();
+		format!(r#"<?xml version="1.0" encoding="UTF-8"?><sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">{}</sitemapindex>"#, locs)
+	}
+
+	#[test]
+	fn files_to_cdn_paths_skips_files_outside_of_the_web_root() {
+		let changed = files(&["public/index.html", "publicity/press.html", "public", "src/main.rs", "public/css/site.css"]);
+
+		assert_eq!(files_to_cdn_paths(&changed, "public", &[]), ["/index.html", "/css/site.css"]);
+		assert_eq!(files_to_cdn_paths(&changed, "/public/", &[]), ["/index.html", "/css/site.css"]);
+	}
+
+	#[test]
+	fn files_to_cdn_paths_normalizes_a_leading_dot_of_the_web_root() {
+		let changed = files(&["src/index.html", "docs/a.html"]);
+
+		assert_eq!(files_to_cdn_paths(&changed, "./src", &[]), ["/index.html"]);
+		assert_eq!(files_to_cdn_paths(&changed, "./src/", &[]), ["/index.html"]);
+		assert_eq!(files_to_cdn_paths(&changed, ".", &[]), ["/src/index.html", "/docs/a.html"]);
+		assert_eq!(files_to_cdn_paths(&changed, "./", &[]), ["/src/index.html", "/docs/a.html"]);
+	}
+
+	#[test]
+	fn git_changed_files_rejects_revisions_starting_with_a_dash() {
+		for (from, to) in [("--output=/tmp/injected.txt", "HEAD"), ("HEAD~1", "-p")] {
+			assert!(git_changed_files(Path::new("."), from, to).is_err(), "{} {}", from, to);
+		}
+	}
+
+	#[test]
+	fn files_to_cdn_paths_maps_all_files_without_web_root() {
+		assert_eq!(files_to_cdn_paths(&files(&["index.html", "docs/a.html"]), "", &[]), ["/index.html", "/docs/a.html"]);
+	}
+
+	#[test]
+	fn files_to_cdn_paths_applies_the_first_matching_rewrite() {
+		let rules = rewrites(&["/static/img/=/images/", "/static/=/assets/", "/static/img/=/never/"]);
+		let changed = files(&["static/img/logo.png", "static/app.js", "index.html"]);
+
+		assert_eq!(files_to_cdn_paths(&changed, "", &rules), ["/images/logo.png", "/assets/app.js", "/index.html"]);
+	}
+
+	#[test]
+	fn files_to_cdn_paths_removes_duplicates_caused_by_rewrites() {
+		let rules = rewrites(&["/v1/=/latest/", "/v2/=/latest/"]);
+
+		assert_eq!(files_to_cdn_paths(&files(&["v1/app.js", "v2/app.js"]), "", &rules), ["/latest/app.js"]);
+	}
+
+	#[test]
+	fn files_to_cdn_paths_encodes_the_paths() {
+		assert_eq!(files_to_cdn_paths(&files(&["public/my file*.html"]), "public", &[]), ["/my%20file%2A.html"]);
+	}
+
+	#[test]
+	fn path_rewrite_requires_absolute_paths() {
+		assert!("/a/=/b/".parse::<PathRewrite>().is_ok());
+		assert!("a/=/b/".parse::<PathRewrite>().is_err());
+		assert!("/a/".parse::<PathRewrite>().is_err());
+	}
+
+	#[test]
+	fn encode_path_encodes_wildcards_and_reserved_characters() {
+		assert_eq!(encode_path("/images/*.png"), "/images/%2A.png");
+		assert_eq!(encode_path("/a b/ü?#%"), "/a%20b/%C3%BC%3F%23%25");
+		assert_eq!(encode_path("/page;v=1,2/(x)+y@z:~"), "/page;v=1,2/(x)+y@z:~");
+	}
+
+	#[test]
+	fn sitemap_urls_reads_the_locs_of_a_urlset() {
+		let dir = temp_dir("urlset", &[("sitemap.xml", &urlset(&["https://example.com/", "https://example.com/blog/a?page=2"]))]);
+
+		assert_eq!(sitemap_urls(&dir.join("sitemap.xml")).unwrap(), ["https://example.com/", "https://example.com/blog/a?page=2"]);
+	}
+
+	#[test]
+	fn sitemap_urls_follows_an_index_to_nested_sitemaps_by_path_and_by_name() {
+		let dir = temp_dir("index", &[
+			("sitemap.xml", &sitemap_index(&["https://example.com/sitemaps/pages.xml", "https://example.com/static/posts.xml"])),
+			("sitemaps/pages.xml", &urlset(&["https://example.com/about"])),
+			("posts.xml", &urlset(&["https://example.com/blog/a", "https://example.com/blog/b"])),
+		]);
+
+		assert_eq!(sitemap_urls(&dir.join("sitemap.xml")).unwrap(), ["https://example.com/about", "https://example.com/blog/a", "https://example.com/blog/b"]);
+	}
+
+	#[test]
+	fn sitemap_urls_reads_indexes_referencing_each_other_once() {
+		let dir = temp_dir("index-cycle", &[
+			("sitemap.xml", &sitemap_index(&["https://example.com/other.xml", "https://example.com/pages.xml"])),
+			("other.xml", &sitemap_index(&["https://example.com/sitemap.xml"])),
+			("pages.xml", &urlset(&["https://example.com/about"])),
+		]);
+
+		assert_eq!(sitemap_urls(&dir.join("sitemap.xml")).unwrap(), ["https://example.com/about"]);
+	}
+
+	#[test]
+	fn sitemap_urls_rejects_missing_nested_sitemaps_and_other_documents() {
+		let dir = temp_dir("invalid", &[
+			("index.xml", &sitemap_index(&["https://example.com/missing.xml"])),
+			("feed.xml", r#"<?xml version="1.0"?><rss version="2.0"></rss>"#),
+			("broken.xml", "<urlset><url>"),
+		]);
+
+		for file in ["index.xml", "feed.xml", "broken.xml", "absent.xml"] {
+			assert!(sitemap_urls(&dir.join(file)).is_err(), "{}", file);
+		}
+	}
+
+	#[test]
+	fn urls_to_paths_keeps_the_query_and_removes_duplicates() {
+		let urls = files(&["https://example.com/", "https://example.com/a%20b?x=1", "https://www.example.com/a%20b?x=1#top", "https://example.com/ü"]);
+
+		assert_eq!(urls_to_paths(&urls).unwrap(), ["/", "/a%20b?x=1", "/%C3%BC"]);
+	}
+
+	#[test]
+	fn urls_to_paths_rejects_invalid_urls() {
+		assert!(urls_to_paths(&files(&["https://example.com/", "/relative/path"])).is_err());
+	}
+
+	#[test]
+	fn dir_file_to_cdn_paths_serves_index_html_as_its_dir() {
+		assert_eq!(dir_file_to_cdn_paths("", "index.html", false), ["/"]);
+		assert_eq!(dir_file_to_cdn_paths("", "blog/index.html", false), ["/blog/"]);
+		assert_eq!(dir_file_to_cdn_paths("/docs/", "blog/2022/index.html", false), ["/docs/blog/2022/"]);
+		assert_eq!(dir_file_to_cdn_paths("/docs", "index.html", false), ["/docs/"]);
+	}
+
+	#[test]
+	fn dir_file_to_cdn_paths_adds_the_index_html_itself_if_requested() {
+		assert_eq!(dir_file_to_cdn_paths("/", "index.html", true), ["/", "/index.html"]);
+		assert_eq!(dir_file_to_cdn_paths("/docs", "blog/index.html", true), ["/docs/blog/", "/docs/blog/index.html"]);
+		assert_eq!(dir_file_to_cdn_paths("/docs", "css/site.css", true), ["/docs/css/site.css"]);
+	}
+
+	#[test]
+	fn dir_file_to_cdn_paths_keeps_other_files() {
+		assert_eq!(dir_file_to_cdn_paths("", "css/site.css", false), ["/css/site.css"]);
+		assert_eq!(dir_file_to_cdn_paths("/docs", "blog/myindex.html", false), ["/docs/blog/myindex.html"]);
+		assert_eq!(dir_file_to_cdn_paths("/docs", "index.htm", false), ["/docs/index.htm"]);
+	}
+
+	#[test]
+	fn dir_file_to_cdn_paths_adds_a_missing_leading_slash_to_the_prefix() {
+		assert_eq!(dir_file_to_cdn_paths("static", "a b.css", false), ["/static/a b.css"]);
+		assert_eq!(dir_file_to_cdn_paths("static/", "index.html", false), ["/static/"]);
+		assert_eq!(dir_file_to_cdn_paths("static/v2", "blog/index.html", true), ["/static/v2/blog/", "/static/v2/blog/index.html"]);
+	}
+
+	#[test]
+	fn parse_size_supports_binary_unit_suffixes() {
+		assert_eq!(parse_size("512"), Ok(512));
+		assert_eq!(parse_size("100K"), Ok(100 * 1024));
+		assert_eq!(parse_size("10m"), Ok(10 * 1024 * 1024));
+		assert_eq!(parse_size(" 1G "), Ok(1024 * 1024 * 1024));
+	}
+
+	#[test]
+	fn parse_size_rejects_invalid_sizes() {
+		for size in ["", "K", "1T", "1.5M", "-1", "99999999999999G"] {
+			assert!(parse_size(size).is_err(), "{}", size);
+		}
+	}
+}
@@ -0,0 +1,277 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use cdn77::{Cdn77Client, fnv1a_hash, ResourceId};
+use cdn77::api_resources::CdnResource;
+use chrono::Utc;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Profile;
+use crate::util::CliError;
+
+/// Resolves the resource references given by the user, which can be an ID, a label, a CNAME, the CDN URL or a URL with one of those hosts.
+/// The resource list is cached per account in the user's cache dir, as it rarely changes.
+pub struct ResourceResolver<'a> {
+	client: &'a Cdn77Client,
+	cache_file: Option<PathBuf>,
+	ttl: Duration,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ResourceCache {
+	fetched_at: i64,
+	resources: Vec<CdnResource>,
+}
+
+impl<'a> ResourceResolver<'a> {
+	/// The cache is keyed by a hash of API base and token, so the token isn't stored. A TTL of zero disables the cache.
+	pub fn new(client: &'a Cdn77Client, api_token: &str, ttl: Duration) -> Self {
+		let key = format!("{}\n{}", client.api_base(), api_token);
+		let cache_file = cache_dir()
+			.filter(|_| !ttl.is_zero())
+			.map(|dir| dir.join(format!("resources-{:016x}.json", fnv1a_hash(key.as_bytes()))));
+
+		ResourceResolver { client, cache_file, ttl }
+	}
+
+	/// The resource given via args, otherwise the single default resource of the profile
+	pub async fn resource_id(&self, reference: &Option<String>, profile: &Profile) -> Result<ResourceId, CliError> {
+		match reference {
+			Some(reference) => self.resolve(reference).await,
+			None => self.resolve(profile.default_resource()?).await,
+		}
+	}
+
+	/// Resolves a comma separated list of references, otherwise the default resources of the profile. `None` if there's neither.
+	pub async fn resource_ids(&self, references: &Option<String>, profile: &Profile) -> Result<Option<Vec<ResourceId>>, CliError> {
+		let references: Vec<&str> = match references {
+			Some(references) => references.split(',').map(|r| r.trim()).filter(|r| !r.is_empty()).collect(),
+			None if !profile.resource_ids.is_empty() => profile.resource_ids.iter().map(|r| r.as_str()).collect(),
+			None => return Ok(None),
+		};
+
+		Ok(Some(self.resolve_all(&references).await?))
+	}
+
+	pub async fn resolve_all<S: AsRef<str>>(&self, references: &[S]) -> Result<Vec<ResourceId>, CliError> {
+		let mut resource_ids = vec![];
+		for reference in references {
+			resource_ids.push(self.resolve(reference.as_ref()).await?);
+		}
+		Ok(resource_ids)
+	}
+
+	/// Numeric references are used as is, without calling the API
+	pub async fn resolve(&self, reference: &str) -> Result<ResourceId, CliError> {
+		if let Ok(resource_id) = reference.parse::<ResourceId>() {
+			return Ok(resource_id);
+		}
+
+		match self.matching_resources(reference).await?.as_slice() {
+			[resource_id] => Ok(*resource_id),
+			[] => Err(CliError::InvalidInput(format!("No CDN resource has the label, CNAME or hostname '{}'", reference))),
+			resource_ids => Err(CliError::InvalidInput(format!("'{}' is ambiguous, it matches the resource IDs {:?}, please use one of them",
+															   reference, resource_ids))),
+		}
+	}
+
+	/// The resource which has the host as CDN URL or CNAME, `None` if no resource of the account does
+	pub async fn owner_of_host(&self, host: &str) -> Result<Option<ResourceId>, CliError> {
+		match self.matching_resources(host).await?.as_slice() {
+			[] => Ok(None),
+			[resource_id] => Ok(Some(*resource_id)),
+			resource_ids => Err(CliError::InvalidInput(format!("The host '{}' is ambiguous, it matches the resource IDs {:?}", host, resource_ids))),
+		}
+	}
+
+	async fn matching_resources(&self, reference: &str) -> Result<Vec<ResourceId>, CliError> {
+		let cached_matches = self.cached_resources()
+			.map(|resources| find_matches(&resources, reference))
+			.filter(|matches| !matches.is_empty());
+
+		match cached_matches {
+			Some(matches) => Ok(matches),
+			// A resource might have been created or renamed since the list was cached
+			None => Ok(find_matches(&self.fetch_resources().await?, reference)),
+		}
+	}
+
+	fn cached_resources(&self) -> Option<Vec<CdnResource>> {
+		let content = fs::read_to_string(self.cache_file.as_ref()?).ok()?;
+		let cache = serde_json::from_str::<ResourceCache>(&content).ok()?;
+		let age = Utc::now().timestamp() - cache.fetched_at;
+
+		match age >= 0 && (age as u64) < self.ttl.as_secs() {
+			true => Some(cache.resources),
+			false => None,
+		}
+	}
+
+	/// Failing to write the cache isn't an error, the next call just fetches the list again
+	async fn fetch_resources(&self) -> Result<Vec<CdnResource>, CliError> {
+		let resources = self.client.list_resources().await?;
+
+		if let Some(cache_file) = &self.cache_file {
+			let cache = ResourceCache { fetched_at: Utc::now().timestamp(), resources };
+			if let Some(dir) = cache_file.parent() {
+				let _ = fs::create_dir_all(dir);
+			}
+			let _ = fs::write(cache_file, serde_json::to_string(&cache).expect("Resources are serializable"));
+			return Ok(cache.resources);
+		}
+
+		Ok(resources)
+	}
+}
+
+/// Labels are compared case-insensitively, as are hostnames. The reference may also be a URL, e.g. `https://cdn.example.com/img/logo.png`.
+fn find_matches(resources: &[CdnResource], reference: &str) -> Vec<ResourceId> {
+	let reference = reference.trim().to_lowercase();
+	let host = Url::parse(&reference).ok()
+		.and_then(|url| url.host_str().map(|h| h.to_string()))
+		.unwrap_or_else(|| reference.trim_end_matches('.').to_string());
+
+	resources.iter()
+		.filter(|r| {
+			r.label.as_deref().map(|l| l.to_lowercase() == reference).unwrap_or(false)
+				|| r.cdn_url.to_lowercase() == host
+				|| r.cnames.iter().any(|c| c.to_lowercase() == host)
+		})
+		.map(|r| r.id)
+		.collect()
+}
+
+/// `$XDG_CACHE_HOME/cdn77`, defaulting to `~/.cache/cdn77`
+fn cache_dir() -> Option<PathBuf> {
+	env::var_os("XDG_CACHE_HOME")
+		.filter(|dir| !dir.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+		.map(|dir| dir.join("cdn77"))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::net::SocketAddr;
+
+	use cdn77::mock_server::{MockServer, MockServerConfig};
+
+	use super::*;
+
+	/// Nothing listens on the discard port, so every API call fails
+	const UNREACHABLE_API_BASE: &str = "http://127.0.0.1:9/v3";
+
+	fn resource(id: ResourceId, label: &str, cnames: &[&str]) -> CdnResource {
+		CdnResource {
+			id,
+			cdn_url: format!("{}.rsc.cdn77.org", id),
+			cnames: cnames.iter().map(|c| c.to_string()).collect(),
+			label: Some(label.to_string()),
+			note: None,
+		}
+	}
+
+	fn resources() -> Vec<CdnResource> {
+		vec![
+			resource(1, "www", &["www.example.com", "example.com"]),
+			resource(2, "Static", &["static.example.com"]),
+			resource(3, "static", &["assets.example.com"]),
+		]
+	}
+
+	fn client(api_base: &str) -> Cdn77Client {
+		Cdn77Client::builder("token").api_base(api_base).build().unwrap()
+	}
+
+	/// A cache file in an empty dir per test, filled with the resources fetched the given seconds ago
+	fn cache_file(name: &str, resources: Vec<CdnResource>, age_secs: i64) -> PathBuf {
+		let dir = env::temp_dir().join(format!("cdn77-resolver-test-{}-{}", std::process::id(), name));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		let file = dir.join("resources.json");
+		let cache = ResourceCache { fetched_at: Utc::now().timestamp() - age_secs, resources };
+		fs::write(&file, serde_json::to_string(&cache).unwrap()).unwrap();
+		file
+	}
+
+	fn resolver<'a>(client: &'a Cdn77Client, cache_file: Option<PathBuf>) -> ResourceResolver<'a> {
+		ResourceResolver { client, cache_file, ttl: Duration::from_secs(300) }
+	}
+
+	#[test]
+	fn find_matches_by_label_cname_and_cdn_url() {
+		let resources = resources();
+
+		assert_eq!(find_matches(&resources, "WWW"), [1]);
+		assert_eq!(find_matches(&resources, "Example.com."), [1]);
+		assert_eq!(find_matches(&resources, "2.rsc.cdn77.org"), [2]);
+		assert_eq!(find_matches(&resources, "https://assets.example.com/img/logo.png"), [3]);
+	}
+
+	#[test]
+	fn find_matches_returns_all_or_no_matches() {
+		let resources = resources();
+
+		assert_eq!(find_matches(&resources, "static"), [2, 3]);
+		assert!(find_matches(&resources, "cdn.example.com").is_empty());
+		assert!(find_matches(&resources, "example").is_empty());
+	}
+
+	#[tokio::test]
+	async fn resolve_uses_numeric_ids_without_the_api() {
+		let client = client(UNREACHABLE_API_BASE);
+
+		assert_eq!(resolver(&client, None).resolve("12345").await.unwrap(), 12345);
+	}
+
+	#[tokio::test]
+	async fn resolve_uses_the_cached_resources() {
+		let client = client(UNREACHABLE_API_BASE);
+		let resolver = resolver(&client, Some(cache_file("cached", resources(), 0)));
+
+		assert_eq!(resolver.resolve("www").await.unwrap(), 1);
+		assert_eq!(resolver.resolve("static.example.com").await.unwrap(), 2);
+		assert_eq!(resolver.owner_of_host("assets.example.com").await.unwrap(), Some(3));
+	}
+
+	#[tokio::test]
+	async fn resolve_rejects_ambiguous_references() {
+		let client = client(UNREACHABLE_API_BASE);
+		let resolver = resolver(&client, Some(cache_file("ambiguous", resources(), 0)));
+
+		match resolver.resolve("static").await {
+			Err(CliError::InvalidInput(message)) => assert!(message.contains("ambiguous"), "{}", message),
+			result => panic!("Unexpected result {:?}", result),
+		}
+	}
+
+	#[tokio::test]
+	async fn resolve_fetches_the_resources_if_the_cache_has_no_match() {
+		let server = MockServer::start(SocketAddr::from(([127, 0, 0, 1], 0)), MockServerConfig::default()).await.unwrap();
+		let client = client(&server.api_base());
+		let cache_file = cache_file("refetch", resources(), 0);
+		let resolver = resolver(&client, Some(cache_file.clone()));
+
+		assert_eq!(resolver.resolve("cdn-12345.example.com").await.unwrap(), 12345);
+		assert!(fs::read_to_string(cache_file).unwrap().contains("cdn-12345.example.com"));
+		assert_eq!(resolver.owner_of_host("unknown.example.com").await.unwrap(), None);
+		match resolver.resolve("unknown").await {
+			Err(CliError::InvalidInput(message)) => assert!(message.contains("No CDN resource"), "{}", message),
+			result => panic!("Unexpected result {:?}", result),
+		}
+	}
+
+	#[test]
+	fn cached_resources_expire_after_the_ttl() {
+		let client = client(UNREACHABLE_API_BASE);
+
+		assert!(resolver(&client, Some(cache_file("fresh", resources(), 290))).cached_resources().is_some());
+		assert!(resolver(&client, Some(cache_file("expired", resources(), 300))).cached_resources().is_none());
+		// A clock which went backwards doesn't keep the cache forever
+		assert!(resolver(&client, Some(cache_file("future", resources(), -60))).cached_resources().is_none());
+		assert!(resolver(&client, None).cached_resources().is_none());
+	}
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::Command;
+
+use crate::util::CliError;
+
+/// Reads the token from a file, which must not be accessible by group or others
+pub fn read_token_file(file: &Path) -> Result<String, CliError> {
+	let metadata = fs::metadata(file)
+		.map_err(|err| CliError::InvalidInput(format!("Cannot read API token file {}: {}", file.display(), err)))?;
+	check_permissions(file, &metadata)?;
+
+	let content = fs::read_to_string(file)
+		.map_err(|err| CliError::InvalidInput(format!("Cannot read API token file {}: {}", file.display(), err)))?;
+	non_empty(content, &format!("API token file {}", file.display()))
+}
+
+/// Reads the token from stdin, e.g. piped from a password manager
+pub fn read_token_stdin() -> Result<String, CliError> {
+	let mut content = String::new();
+	io::stdin().read_to_string(&mut content)
+		.map_err(|err| CliError::InvalidInput(format!("Cannot read API token from stdin: {}", err)))?;
+	non_empty(content, "API token from stdin")
+}
+
+/// Runs the command via the shell and uses its stdout as token, stderr is passed through (e.g. for login prompts)
+pub fn run_token_command(command: &str) -> Result<String, CliError> {
+	let output = shell(command)
+		.stderr(std::process::Stdio::inherit())
+		.output()
+		.map_err(|err| CliError::InvalidInput(format!("Cannot run token_command '{}': {}", command, err)))?;
+
+	if !output.status.success() {
+		return Err(CliError::InvalidInput(format!("token_command '{}' failed with {}", command, output.status)));
+	}
+
+	let content = String::from_utf8(output.stdout)
+		.map_err(|_| CliError::InvalidInput(format!("token_command '{}' printed invalid UTF-8", command)))?;
+	non_empty(content, &format!("Output of token_command '{}'", command))
+}
+
+#[cfg(unix)]
+fn check_permissions(file: &Path, metadata: &fs::Metadata) -> Result<(), CliError> {
+	use std::os::unix::fs::PermissionsExt;
+
+	let mode = metadata.permissions().mode();
+	if mode & 0o077 != 0 {
+		return Err(CliError::InvalidInput(format!("API token file {} is accessible by others (mode {:o}), please run: chmod 600 {}",
+												  file.display(), mode & 0o777, file.display())));
+	}
+
+	Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_file: &Path, _metadata: &fs::Metadata) -> Result<(), CliError> {
+	Ok(())
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+	let mut shell = Command::new("sh");
+	shell.arg("-c").arg(command);
+	shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+	let mut shell = Command::new("cmd");
+	shell.arg("/C").arg(command);
+	shell
+}
+
+fn non_empty(content: String, source: &str) -> Result<String, CliError> {
+	let token = content.trim();
+	if token.is_empty() {
+		return Err(CliError::InvalidInput(format!("{} is empty", source)));
+	}
+
+	Ok(token.to_string())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use std::os::unix::fs::PermissionsExt;
+	use std::path::PathBuf;
+
+	use super::*;
+
+	fn token_file(name: &str, content: &str, mode: u32) -> PathBuf {
+		let file = std::env::temp_dir().join(format!("cdn77-token-test-{}-{}", std::process::id(), name));
+		fs::write(&file, content).unwrap();
+		fs::set_permissions(&file, fs::Permissions::from_mode(mode)).unwrap();
+		file
+	}
+
+	#[test]
+	fn read_token_file_accepts_files_only_accessible_by_the_owner() {
+		assert_eq!(read_token_file(&token_file("owner-rw", "secret\n", 0o600)).unwrap(), "secret");
+		assert_eq!(read_token_file(&token_file("owner-r", " secret ", 0o400)).unwrap(), "secret");
+	}
+
+	#[test]
+	fn read_token_file_rejects_files_accessible_by_others() {
+		for (name, mode) in [("group-r", 0o640), ("other-r", 0o604), ("group-w", 0o620), ("all", 0o777)] {
+			match read_token_file(&token_file(name, "secret", mode)) {
+				Err(CliError::InvalidInput(message)) => assert!(message.contains("chmod 600"), "{}", message),
+				result => panic!("Unexpected result for mode {:o}: {:?}", mode, result),
+			}
+		}
+	}
+
+	#[test]
+	fn read_token_file_rejects_empty_and_missing_files() {
+		assert!(read_token_file(&token_file("empty", " \n", 0o600)).is_err());
+		assert!(read_token_file(Path::new("/nonexistent/cdn77-token")).is_err());
+	}
+}
@@ -1,81 +1,132 @@
-use std::process;
+use std::fmt::{Display, Formatter};
+
+use cdn77::{ApiErrorResponse, Cdn77Error, DryRunRequest};
 use chrono::NaiveDateTime;
+use serde::Serialize;
 
-use reqwest::{RequestBuilder, Response, StatusCode};
+use crate::output::ErrorFormat;
+use crate::{EXIT_CODE_API_EXPECTED_ERROR, EXIT_CODE_API_UNEXPECTED_ERROR, EXIT_CODE_INVALID_INPUT, EXIT_CODE_WAIT_TIMEOUT};
 
-use crate::{EXIT_CODE_API_EXPECTED_ERROR, EXIT_CODE_API_UNEXPECTED_ERROR, EXIT_CODE_INVALID_INPUT};
+/// Errors of a CLI command, these are mapped to an exit code in `main`
+#[derive(Debug)]
+pub enum CliError {
+	/// The user provided some unexpected/invalid input
+	InvalidInput(String),
+	/// The API operation failed, the message replaces the default message of the error if set
+	Api { error: Cdn77Error, message: Option<String> },
+	/// A job waited for via `--wait` ended in the failed state
+	JobFailed(String),
+	/// The jobs weren't done before the `--wait-timeout`
+	WaitTimeout(String),
+}
 
-/// An alias for the resource ID type
-pub type ResourceId = u64;
+impl CliError {
+	/// Wraps an API error with a message specific to the command
+	pub fn api_with_message(error: Cdn77Error, message: String) -> Self {
+		CliError::Api { error, message: Some(message) }
+	}
 
-/// These are the default status codes as defined here: https://client.cdn77.com/support/api-reference/v3/introduction
-/// Unfortunately, some codes have a duplicate meaning for some API operations
-/// For example, 403 might signify "bad credentials" or "purge-all not allowed on resource"
-/// So this handler is only invoked after the expected API operation specific codes have been handled.
-pub async fn handle_default_response_status_codes(response: Response) {
-	match response.status() {
-		StatusCode::UNAUTHORIZED => {
-			eprintln!("Got 401/unauthorized. Please check your credentials.");
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
-		}
-		StatusCode::FORBIDDEN => {
-			eprintln!("Got 403/forbidden. Please check your credentials or the API operation args.");
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
+	/// These are the default status codes as defined here: https://client.cdn77.com/support/api-reference/v3/introduction
+	/// Unfortunately, some codes have a duplicate meaning for some API operations
+	/// For example, 403 might signify "bad credentials" or "purge-all not allowed on resource"
+	/// So commands handle the expected API operation specific codes themselves and attach a message.
+	pub fn exit_code(&self) -> i32 {
+		match self {
+			CliError::InvalidInput(_) => EXIT_CODE_INVALID_INPUT,
+			CliError::JobFailed(_) => EXIT_CODE_API_UNEXPECTED_ERROR,
+			CliError::WaitTimeout(_) => EXIT_CODE_WAIT_TIMEOUT,
+			CliError::Api { error, .. } => match error {
+				Cdn77Error::InvalidConfig(_) => EXIT_CODE_INVALID_INPUT,
+				Cdn77Error::Unauthorized { .. } | Cdn77Error::Forbidden { .. } | Cdn77Error::NotFound { .. } => EXIT_CODE_API_EXPECTED_ERROR,
+				Cdn77Error::Validation { .. }
+				| Cdn77Error::UnexpectedStatus { .. }
+				| Cdn77Error::Transport(_)
+				| Cdn77Error::Deserialization(_)
+				| Cdn77Error::Fixture(_)
+				// Dry runs are reported as success in `run_command`, getting here is a bug
+				| Cdn77Error::DryRun(_) => EXIT_CODE_API_UNEXPECTED_ERROR,
+			},
 		}
-		StatusCode::NOT_FOUND => {
-			println!("The requested resource was not found. Please validate your args.");
-			process::exit(EXIT_CODE_API_EXPECTED_ERROR);
-		}
-		StatusCode::METHOD_NOT_ALLOWED => {
-			eprintln!("Received 405/MethodNotAllowed. This might be an issue with an outdated client due to API changes.");
-			process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-		}
-		StatusCode::UNPROCESSABLE_ENTITY => {
-			eprintln!("Received 422/UnprocessableEntity. This might be an issue with this client, please check for an update.");
-			process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
+	}
+
+	/// Stable identifier of the kind of error, so automation doesn't need to parse messages
+	pub fn code(&self) -> &'static str {
+		match self {
+			CliError::InvalidInput(_) => "invalid_input",
+			CliError::JobFailed(_) => "job_failed",
+			CliError::WaitTimeout(_) => "wait_timeout",
+			CliError::Api { error, .. } => match error {
+				Cdn77Error::InvalidConfig(_) => "invalid_config",
+				Cdn77Error::Unauthorized { .. } => "unauthorized",
+				Cdn77Error::Forbidden { .. } => "forbidden",
+				Cdn77Error::NotFound { .. } => "not_found",
+				Cdn77Error::Validation { .. } => "validation_failed",
+				Cdn77Error::UnexpectedStatus { .. } => "unexpected_status",
+				Cdn77Error::Transport(_) => "transport",
+				Cdn77Error::Deserialization(_) => "deserialization",
+				Cdn77Error::Fixture(_) => "fixture",
+				Cdn77Error::DryRun(_) => "dry_run",
+			},
 		}
-		code => {
-			let body: String = response.text().await.unwrap_or_else(|_| "FAILED TO READ RESPONSE, EMPTY?".to_string());
-			eprintln!("Received unexpected/unknown status code={}, please check the response for an explanation: {}", code, body);
-			process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
+	}
+
+	/// Prints the error to stderr in the given format
+	pub fn report(&self, format: ErrorFormat) {
+		match format {
+			ErrorFormat::Text => eprintln!("{}", self),
+			ErrorFormat::Json => {
+				let error = match self {
+					CliError::InvalidInput(_) | CliError::JobFailed(_) | CliError::WaitTimeout(_) => None,
+					CliError::Api { error, .. } => Some(error),
+				};
+				let report = ErrorReport {
+					code: self.code(),
+					http_status: error.and_then(|e| e.status()).map(|s| s.as_u16()),
+					message: self.to_string(),
+					api_errors: error.and_then(|e| e.api_errors()),
+					request_url: error.and_then(|e| e.request_url()),
+				};
+				eprintln!("{}", serde_json::to_string(&report).expect("Error reports are serializable"));
+			}
 		}
-	};
+	}
 }
 
-pub fn parse_date_time_or_exit(input: &str, error_msg: &str) -> NaiveDateTime {
-	NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M")
-		.unwrap_or_else(|_| {
-			println!("{}", error_msg);
-			process::exit(EXIT_CODE_INVALID_INPUT)
-		})
+/// The JSON layout of `--error-format json`
+#[derive(Serialize)]
+struct ErrorReport<'a> {
+	code: &'a str,
+	http_status: Option<u16>,
+	message: String,
+	api_errors: Option<&'a ApiErrorResponse>,
+	request_url: Option<&'a str>,
 }
 
-pub fn parse_resource_ids_optional(input: &Option<String>) -> Option<Vec<ResourceId>> {
-	match input {
-		Some(r) => {
-			let resource_ids = r.split(',')
-				.map(|r| r.trim())
-				.filter(|r| !r.is_empty())
-				.map(|s| s.parse::<ResourceId>().expect("At least one resource id is malformed"))
-				.collect();
-			Some(resource_ids)
+impl Display for CliError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			CliError::InvalidInput(msg) | CliError::JobFailed(msg) | CliError::WaitTimeout(msg) => write!(f, "{}", msg),
+			CliError::Api { message: Some(message), .. } => write!(f, "{}", message),
+			CliError::Api { error, message: None } => write!(f, "{}", error),
 		}
-		None => None,
 	}
 }
 
-pub async fn read_body_or_return_default_error_text(response: Response) -> String {
-	response.text().await.unwrap_or("<CANNOT READ BODY>".to_string())
+impl From<Cdn77Error> for CliError {
+	fn from(error: Cdn77Error) -> Self {
+		CliError::Api { error, message: None }
+	}
 }
 
-pub async fn send_http_request_return_response_or_exit(request: RequestBuilder) -> Response {
-	let response = request.send().await;
+pub fn parse_date_time(input: &str, error_msg: &str) -> Result<NaiveDateTime, CliError> {
+	NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M")
+		.map_err(|_| CliError::InvalidInput(error_msg.to_string()))
+}
 
-	match response {
-		Ok(r) => r,
-		Err(err) => {
-			eprintln!("Failed to get response HTTP request, e={:?}", err);
-			process::exit(EXIT_CODE_API_UNEXPECTED_ERROR);
-		}
+/// Text format of a request which wasn't sent due to `--dry-run`
+pub fn print_dry_run_request(request: &DryRunRequest) {
+	println!("Dry run, the request was not sent:\n{} {}", request.method, request.url);
+	if let Some(body) = &request.body {
+		println!("{}", serde_json::to_string_pretty(body).unwrap());
 	}
 }
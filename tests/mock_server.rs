@@ -0,0 +1,381 @@
+//! Runs the CLI binary against the in-memory mock of the API, e.g. to check the retry behaviour, the exit codes and record/replay.
+
+use std::fs;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+use cdn77::mock_server::{InjectedFailure, MockServer, MockServerConfig};
+
+const RESOURCE_ID: &str = "12345";
+
+async fn start_mock() -> MockServer {
+	MockServer::start(SocketAddr::from(([127, 0, 0, 1], 0)), MockServerConfig::default()).await.unwrap()
+}
+
+fn failure(status: u16, path_prefix: &str, times: u32) -> InjectedFailure {
+	InjectedFailure { status, path_prefix: Some(path_prefix.to_string()), times: Some(times) }
+}
+
+/// An empty dir per test, used as HOME and working dir, so neither profiles nor a `.env` of the developer are picked up
+fn temp_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("cdn77-client-test-{}-{}", std::process::id(), name));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+/// The CLI with a clean environment. Running it blocks, so the tests need the multi-threaded runtime for the mock to keep serving.
+fn cli(home: &PathBuf) -> Command {
+	let mut command = Command::new(env!("CARGO_BIN_EXE_cdn77-client"));
+	command.env_clear()
+		.env("HOME", home)
+		.env("XDG_CONFIG_HOME", home.join(".config"))
+		.env("XDG_CACHE_HOME", home.join(".cache"))
+		.current_dir(home);
+	command
+}
+
+fn run_cli(home: &PathBuf, api_base: &str, args: &[&str]) -> Output {
+	cli(home)
+		.args(["--api-base", api_base, "-a", "test-token"])
+		.args(args)
+		.output()
+		.unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retries_purge_on_429() {
+	let server = start_mock().await;
+	server.inject_failure(failure(429, "/cdn/12345/job/purge", 2));
+	let home = temp_dir("retries-purge-on-429");
+
+	let output = run_cli(&home, &server.api_base(), &["--retries", "2", "jobs", "purge", "-i", RESOURCE_ID, "-p", "/index.html"]);
+
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(server.job_ids().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn gives_up_after_the_retries_on_429() {
+	let server = start_mock().await;
+	server.inject_failure(failure(429, "/cdn/12345/job/purge", 2));
+	let home = temp_dir("gives-up-on-429");
+
+	let output = run_cli(&home, &server.api_base(), &["--retries", "1", "jobs", "purge", "-i", RESOURCE_ID, "-p", "/index.html"]);
+
+	assert_eq!(output.status.code(), Some(4));
+	assert!(server.job_ids().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn retries_idempotent_requests_on_5xx() {
+	let server = start_mock().await;
+	server.inject_failure(failure(503, "/cdn", 2));
+	let home = temp_dir("retries-idempotent-on-5xx");
+
+	let output = run_cli(&home, &server.api_base(), &["--retries", "2", "--retry-max-wait", "1", "resources", "list"]);
+
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	assert!(String::from_utf8_lossy(&output.stdout).contains(RESOURCE_ID));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn doesnt_retry_purge_on_5xx() {
+	let server = start_mock().await;
+	server.inject_failure(failure(500, "/cdn/12345/job/purge", 1));
+	let home = temp_dir("no-purge-retry-on-5xx");
+
+	let output = run_cli(&home, &server.api_base(), &["--retries", "2", "--retry-max-wait", "1", "jobs", "purge", "-i", RESOURCE_ID, "-p", "/index.html"]);
+
+	// The purge might have been processed, so a retry could create a second job
+	assert_eq!(output.status.code(), Some(4));
+	assert!(server.job_ids().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn maps_api_errors_to_exit_codes() {
+	let server = start_mock().await;
+	let home = temp_dir("exit-codes");
+
+	server.inject_failure(failure(422, "/cdn/12345/job/purge", 1));
+	let validation = run_cli(&home, &server.api_base(), &["--retries", "2", "jobs", "purge", "-i", RESOURCE_ID, "-p", "/index.html"]);
+	assert_eq!(validation.status.code(), Some(4));
+	assert!(server.job_ids().is_empty());
+
+	let not_found = run_cli(&home, &server.api_base(), &["jobs", "purge", "-i", "99999", "-p", "/index.html"]);
+	assert_eq!(not_found.status.code(), Some(3));
+
+	server.inject_failure(failure(401, "/cdn", 1));
+	let unauthorized = run_cli(&home, &server.api_base(), &["resources", "list"]);
+	assert_eq!(unauthorized.status.code(), Some(3));
+
+	let invalid_input = run_cli(&home, &server.api_base(), &["jobs", "purge", "-i", RESOURCE_ID, "-p", "/index.html", "--batch-size", "0"]);
+	assert_eq!(invalid_input.status.code(), Some(2));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn uses_the_token_of_the_selected_profile_over_the_env() {
+	let config = MockServerConfig { api_token: Some("staging-token".to_string()), ..MockServerConfig::default() };
+	let server = MockServer::start(SocketAddr::from(([127, 0, 0, 1], 0)), config).await.unwrap();
+	let home = temp_dir("profile-token");
+	fs::write(home.join("config.toml"), format!("[profiles.staging]\napi_token = \"staging-token\"\napi_base = \"{}\"\n", server.api_base())).unwrap();
+	// The ambient token, e.g. of the prod account, is only used without an explicitly selected profile
+	fs::write(home.join(".env"), "CDN77_API_TOKEN=prod-token\n").unwrap();
+
+	let selected = cli(&home).args(["resources", "list", "--config", "config.toml", "--profile", "staging"]).output().unwrap();
+	assert_eq!(selected.status.code(), Some(0), "{}", String::from_utf8_lossy(&selected.stderr));
+
+	let ambient = cli(&home).args(["resources", "list", "--api-base", &server.api_base()]).output().unwrap();
+	assert_eq!(ambient.status.code(), Some(3));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replays_recorded_responses() {
+	let server = start_mock().await;
+	let home = temp_dir("replay");
+	let fixtures = home.join("fixtures");
+	let fixtures = fixtures.to_str().unwrap();
+
+	let recorded = run_cli(&home, &server.api_base(), &["--record", fixtures, "resources", "list"]);
+	assert_eq!(recorded.status.code(), Some(0), "{}", String::from_utf8_lossy(&recorded.stderr));
+	drop(server);
+
+	// Nothing listens on the discard port, so the output can only come from the fixtures
+	let replayed = run_cli(&home, "http://127.0.0.1:9/v3", &["--replay", fixtures, "resources", "list"]);
+	assert_eq!(replayed.status.code(), Some(0), "{}", String::from_utf8_lossy(&replayed.stderr));
+	assert_eq!(String::from_utf8_lossy(&replayed.stdout), String::from_utf8_lossy(&recorded.stdout));
+
+	let token_leaked = fs::read_dir(home.join("fixtures")).unwrap()
+		.map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+		.any(|fixture| fixture.contains("test-token"));
+	assert!(!token_leaked);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prefetches_only_the_sitemap_urls_on_hosts_of_the_resource() {
+	let server = start_mock().await;
+	let home = temp_dir("sitemap-hosts");
+	let urls = ["https://cdn-12345.example.com/served", "https://12345.rsc.cdn77.org/also-served", "https://www.example.org/skipped"];
+	let locs: String = urls.iter().map(|u| format!("<url><loc>{}</loc></url>", u)).collect();
+	fs::write(home.join("sitemap.xml"), format!("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">{}</urlset>", locs)).unwrap();
+
+	let output = run_cli(&home, &server.api_base(), &["--dry-run", "-o", "json", "jobs", "prefetch-sitemap", "-i", RESOURCE_ID, "-f", "sitemap.xml"]);
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.contains("/served") && stdout.contains("/also-served") && !stdout.contains("/skipped"), "{}", stdout);
+	assert!(String::from_utf8_lossy(&output.stderr).contains("Skipping the URLs on www.example.org"));
+
+	let any_host = run_cli(&home, &server.api_base(), &["--dry-run", "-o", "json", "jobs", "prefetch-sitemap", "-i", RESOURCE_ID, "-f", "sitemap.xml", "--any-host"]);
+	assert!(String::from_utf8_lossy(&any_host.stdout).contains("/skipped"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn purges_urls_on_their_resources() {
+	let server = start_mock().await;
+	let home = temp_dir("purge-urls");
+
+	let output = run_cli(&home, &server.api_base(), &["jobs", "purge", "--urls", "https://cdn-12345.example.com/a?v=1,2,https://12345.rsc.cdn77.org/b",
+													   "--url", "https://cdn-12345.example.com/c"]);
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	assert!(stdout.contains("resource_id=12345 path=/a?v=1,2") && stdout.contains("path=/b") && stdout.contains("path=/c"), "{}", stdout);
+	assert_eq!(server.job_ids().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_a_sent_purge_as_success_if_its_fixture_cannot_be_written() {
+	let server = start_mock().await;
+	let home = temp_dir("record-failure");
+	let args = ["jobs", "purge", "-i", RESOURCE_ID, "-p", "/index.html"];
+
+	let recorded = run_cli(&home, &server.api_base(), &[&["--record", "fixtures"][..], &args].concat());
+	assert_eq!(recorded.status.code(), Some(0), "{}", String::from_utf8_lossy(&recorded.stderr));
+
+	// Dirs in place of the fixture files make writing them fail
+	for entry in fs::read_dir(home.join("fixtures")).unwrap() {
+		fs::create_dir_all(home.join("blocked").join(entry.unwrap().file_name())).unwrap();
+	}
+	let output = run_cli(&home, &server.api_base(), &[&["--record", "blocked"][..], &args].concat());
+
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	assert_eq!(server.job_ids().len(), 2);
+	assert!(String::from_utf8_lossy(&output.stderr).contains("Cannot record the fixture"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lists_the_paths_of_a_failed_batch_and_the_other_jobs() {
+	let server = start_mock().await;
+	let home = temp_dir("failed-batch");
+	let args = ["jobs", "purge", "-i", RESOURCE_ID, "-p", "/a,/b,/c,/d,/e", "--batch-size", "2"];
+
+	// Sequentially, the first of the 3 batches gets the error
+	server.inject_failure(failure(500, "/cdn/12345/job/purge", 1));
+	let output = run_cli(&home, &server.api_base(), &[&args[..], &["--concurrency", "1"]].concat());
+
+	assert_eq!(output.status.code(), Some(4), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let job_ids = server.job_ids();
+	assert_eq!(job_ids.len(), 2);
+	assert!(stdout.contains("Batch #1: ResourceID=12345 FAILED PathsCount=2") && stdout.contains("Paths=[\"/a\", \"/b\"]"), "{}", stdout);
+	assert!(stdout.contains(&format!("Created 2 of 3 jobs\nJobIDs={}", job_ids.join(","))), "{}", stdout);
+	assert!(String::from_utf8_lossy(&output.stderr).contains("1 of 3 batches failed"));
+
+	// Concurrently, any batch can get the error, but still only that one fails
+	server.inject_failure(failure(500, "/cdn/12345/job/purge", 1));
+	let output = run_cli(&home, &server.api_base(), &[&args[..], &["--concurrency", "2"]].concat());
+
+	assert_eq!(output.status.code(), Some(4), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let new_job_ids = &server.job_ids()[2..];
+	assert_eq!(new_job_ids.len(), 2);
+	assert_eq!(stdout.matches("FAILED").count(), 1, "{}", stdout);
+	assert!(new_job_ids.iter().all(|job_id| stdout.contains(&format!("JobID={}", job_id))), "{}", stdout);
+	assert!(stdout.contains("Created 2 of 3 jobs"), "{}", stdout);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn waits_until_the_job_is_done() {
+	let config = MockServerConfig { job_duration: Duration::from_secs(1), ..MockServerConfig::default() };
+	let server = MockServer::start(SocketAddr::from(([127, 0, 0, 1], 0)), config).await.unwrap();
+	let home = temp_dir("wait-done");
+
+	let output = run_cli(&home, &server.api_base(), &["jobs", "purge", "-i", RESOURCE_ID, "-p", "/index.html", "--wait", "--poll-interval", "1"]);
+
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	// The progress goes to stderr, so stdout can still be parsed
+	assert!(stderr.contains("Waiting for 1 of 1 jobs") && stderr.contains("All 1 jobs are done"), "{}", stderr);
+	assert!(!stdout.contains("Waiting") && !stdout.contains("done"), "{}", stdout);
+	assert!(stdout.contains(&server.job_ids()[0]), "{}", stdout);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn exits_with_5_if_waiting_for_the_job_times_out() {
+	let config = MockServerConfig { job_duration: Duration::from_secs(60), ..MockServerConfig::default() };
+	let server = MockServer::start(SocketAddr::from(([127, 0, 0, 1], 0)), config).await.unwrap();
+	let home = temp_dir("wait-timeout");
+
+	let output = run_cli(&home, &server.api_base(), &["jobs", "purge", "-i", RESOURCE_ID, "-p", "/index.html", "--wait", "--poll-interval", "1",
+													   "--wait-timeout", "1"]);
+
+	assert_eq!(output.status.code(), Some(5), "{}", String::from_utf8_lossy(&output.stderr));
+	assert!(String::from_utf8_lossy(&output.stderr).contains("Timed out after 1s"));
+	assert!(!String::from_utf8_lossy(&output.stdout).contains("Waiting"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn never_logs_the_token() {
+	let server = start_mock().await;
+	let home = temp_dir("log-token");
+
+	let output = run_cli(&home, &server.api_base(), &["-vv", "--log-format", "json", "jobs", "purge", "-i", RESOURCE_ID, "-p", "/index.html"]);
+
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	let logs: Vec<serde_json::Value> = stderr.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+	let logged = |message: &str| logs.iter().any(|log| log["fields"]["message"] == message && log["fields"]["headers"].is_string());
+	assert!(logged("Request") && logged("Response"), "{}", stderr);
+	assert!(stderr.contains(r#"\"authorization\": \"Bearer <REDACTED>\""#), "{}", stderr);
+	assert!(!stderr.contains("test-token"), "{}", stderr);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refuses_purge_all_on_a_protected_resource() {
+	let server = start_mock().await;
+	let home = temp_dir("purge-all-protected");
+	fs::write(home.join("config.toml"), "[profiles.prod]\nprotected_resource_ids = [12345]\n").unwrap();
+	let args = ["jobs", "purge-all", "-i", RESOURCE_ID, "--yes", "--config", "config.toml", "--profile", "prod"];
+
+	let refused = run_cli(&home, &server.api_base(), &args);
+	assert_eq!(refused.status.code(), Some(2));
+	assert!(String::from_utf8_lossy(&refused.stderr).contains("--force-protected"));
+	assert!(server.job_ids().is_empty());
+
+	let forced = run_cli(&home, &server.api_base(), &[&args[..], &["--force-protected"]].concat());
+	assert_eq!(forced.status.code(), Some(0), "{}", String::from_utf8_lossy(&forced.stderr));
+	assert_eq!(server.job_ids().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn requires_yes_for_purge_all_without_a_terminal() {
+	let server = start_mock().await;
+	let home = temp_dir("purge-all-yes");
+
+	// The stdin of the CLI is empty, so not a terminal to ask for confirmation
+	let refused = run_cli(&home, &server.api_base(), &["jobs", "purge-all", "-i", RESOURCE_ID]);
+	assert_eq!(refused.status.code(), Some(2));
+	assert!(String::from_utf8_lossy(&refused.stderr).contains("use --yes"));
+	assert!(server.job_ids().is_empty());
+
+	let confirmed = run_cli(&home, &server.api_base(), &["jobs", "purge-all", "-i", RESOURCE_ID, "--yes"]);
+	assert_eq!(confirmed.status.code(), Some(0), "{}", String::from_utf8_lossy(&confirmed.stderr));
+	assert_eq!(server.job_ids().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn doesnt_send_jobs_on_a_dry_run() {
+	let server = start_mock().await;
+	let home = temp_dir("dry-run");
+	let runs: [(&[&str], &str, &str); 3] = [
+		(&["jobs", "purge", "-i", RESOURCE_ID, "-p", "/index.html"], "/cdn/12345/job/purge", "/index.html"),
+		(&["jobs", "prefetch", "-i", RESOURCE_ID, "-p", "/app.js"], "/cdn/12345/job/prefetch", "/app.js"),
+		(&["jobs", "purge-all", "-i", RESOURCE_ID], "/cdn/12345/job/purge-all", ""),
+	];
+
+	for (args, path, body) in runs {
+		let output = run_cli(&home, &server.api_base(), &[&["--dry-run"][..], args].concat());
+
+		assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+		let stdout = String::from_utf8_lossy(&output.stdout);
+		assert!(stdout.contains("the request was not sent"), "{}", stdout);
+		assert!(stdout.contains(&format!("POST {}{}", server.api_base(), path)) && stdout.contains(body), "{}", stdout);
+	}
+	assert!(server.job_ids().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn purges_the_paths_piped_to_stdin() {
+	let server = start_mock().await;
+	let home = temp_dir("paths-stdin");
+
+	let mut child = cli(&home)
+		.args(["--api-base", &server.api_base(), "-a", "test-token", "jobs", "purge", "-i", RESOURCE_ID, "--paths-file", "-"])
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.unwrap();
+	child.stdin.take().unwrap().write_all(b"# Release 1.2\n/index.html\n\n/images/a,b.png\n").unwrap();
+	let output = child.wait_with_output().unwrap();
+
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	assert!(String::from_utf8_lossy(&output.stdout).contains("Paths=2/[\"/index.html\", \"/images/a,b.png\"]"),
+			"{}", String::from_utf8_lossy(&output.stdout));
+	assert_eq!(server.job_ids().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn watches_jobs_as_one_json_line_per_refresh() {
+	let config = MockServerConfig { job_duration: Duration::from_secs(1), ..MockServerConfig::default() };
+	let server = MockServer::start(SocketAddr::from(([127, 0, 0, 1], 0)), config).await.unwrap();
+	let home = temp_dir("watch-json");
+	let purged = run_cli(&home, &server.api_base(), &["jobs", "purge", "-i", RESOURCE_ID, "-p", "/index.html"]);
+	assert_eq!(purged.status.code(), Some(0), "{}", String::from_utf8_lossy(&purged.stderr));
+
+	let output = run_cli(&home, &server.api_base(), &["-o", "json", "jobs", "watch", "-i", RESOURCE_ID, "-t", "purge", "--interval", "1", "--until-done"]);
+
+	assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let refreshes: Vec<serde_json::Value> = stdout.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+	assert!(refreshes.len() >= 2, "{}", stdout);
+	assert_eq!(refreshes[0][0]["state"], "queued");
+	let last = &refreshes[refreshes.len() - 1][0];
+	assert_eq!(last["id"], server.job_ids()[0]);
+	assert_eq!(last["state"], "done");
+	assert_eq!(last["previous_state"], "queued");
+	assert!(String::from_utf8_lossy(&output.stderr).contains("All watched jobs are finished"));
+}